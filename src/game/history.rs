@@ -0,0 +1,93 @@
+//! Undo and redo for practice modes.
+
+use super::Game;
+
+/// Undo and redo for practice modes. Each entry is a full copy of the game
+/// from just before a shape was locked.
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Game>,
+    redo: Vec<Game>,
+}
+
+impl History {
+    /// An empty history, with nothing to undo or redo
+    pub fn new() -> Self {
+        History::default()
+    }
+
+    /// Keeps `before` as an undo point if a shape was locked since it was
+    /// taken. A new placement drops anything that could be redone.
+    pub fn record(&mut self, before: Game, after: &Game) {
+        if after.pieces == before.pieces {
+            return;
+        }
+        self.undo.push(before);
+        self.redo.clear();
+    }
+
+    /// Puts `game` back to before the last placement
+    pub fn undo(&mut self, game: &mut Game) {
+        if let Some(previous) = self.undo.pop() {
+            self.redo.push(std::mem::replace(game, previous));
+        }
+    }
+
+    /// Puts back the placement undone last
+    pub fn redo(&mut self, game: &mut Game) {
+        if let Some(next) = self.redo.pop() {
+            self.undo.push(std::mem::replace(game, next));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::mode::Mode;
+    use crate::game::HEIGHT;
+
+    fn place(game: &mut Game, history: &mut History) {
+        let before = game.clone();
+        game.update();
+        game.drop_shape();
+        history.record(before, game);
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut game = Game::with_seed(Mode::Zen, 7);
+        let mut history = History::new();
+        place(&mut game, &mut history);
+        let placed = game.clone();
+        place(&mut game, &mut history);
+        assert_eq!(game.pieces, 2);
+
+        history.undo(&mut game);
+        assert_eq!(game.pieces, 1);
+        assert_eq!(game.board_ref(), placed.board_ref());
+        assert_eq!(game.next_shape, placed.next_shape);
+        history.undo(&mut game);
+        assert_eq!(game.pieces, 0);
+        assert!(game.board_ref()[HEIGHT - 1].iter().all(|&x| x == 0));
+        // Nothing left to undo
+        history.undo(&mut game);
+        assert_eq!(game.pieces, 0);
+
+        history.redo(&mut game);
+        history.redo(&mut game);
+        assert_eq!(game.pieces, 2);
+    }
+
+    #[test]
+    fn test_new_placement_clears_redo() {
+        let mut game = Game::with_seed(Mode::Zen, 7);
+        let mut history = History::new();
+        place(&mut game, &mut history);
+        history.undo(&mut game);
+        place(&mut game, &mut history);
+        history.redo(&mut game);
+        assert_eq!(game.pieces, 1);
+        assert_eq!(history.undo.len(), 1);
+    }
+}
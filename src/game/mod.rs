@@ -1,91 +1,302 @@
+//! The game engine: board, shapes, modes and everything needed to play,
+//! save and replay a game without a terminal.
+
+pub mod history;
+pub mod mode;
+pub mod playlist;
+pub mod randomizer;
+pub mod replay;
+pub mod rotation;
+pub mod save;
 pub mod shapes;
 pub mod sound;
+pub mod stats;
+pub mod synth;
+use mode::Mode;
+use randomizer::Randomizer;
+use rotation::RotationSystem;
 use shapes::{Shape, ShapeTrait, ShapeType};
-use std::time::Instant;
+use stats::Stats;
+use std::time::Duration;
+/// Board width in cells
 pub const WIDTH: usize = 12;
+/// Board height in cells
 pub const HEIGHT: usize = 20;
+/// Size of the next and hold preview boards
 pub const PSIZE: usize = 4;
-const FALL_RATE: u128 = 750;
+/// The engine runs in fixed frames so a game is fully decided by its seed
+/// and the frames its actions happened on.
+pub const FPS: u64 = 60;
+const FALL_RATE: u64 = 45;
+/// Lines needed to go up a level
+pub const LINES_PER_LEVEL: usize = 10;
+/// How long the stack stays visible after a line clear in modes that hide it
+const REVEAL_TIME: u64 = 60;
 
-#[derive(Debug, PartialEq)]
+/// A single step of the active shape
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub enum Move {
+    /// One column left
     Left,
+    /// One column right
     Right,
+    /// One row down, a soft drop
     Down,
+    /// A turn to the next direction
     Rotate,
 }
 
+/// A player input that changes the game
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Action {
+    /// Moves or rotates the active shape
+    Move(Move),
+    /// Drops the active shape to the bottom and locks it
+    Drop,
+    /// Swaps the active shape with the held one
+    Hold,
+    /// Pauses or resumes the game
+    Pause,
+    /// Switches gravity on or off in zen mode
+    ToggleGravity,
+    /// Picks the next shape in zen mode
+    ChooseNext(ShapeType),
+}
+
+/// Something that happened in the game, for front ends to react to with
+/// sound or animation. See [`Game::take_events`].
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Event {
+    /// The active shape moved left or right
+    Move,
+    /// The active shape rotated
+    Rotate,
+    /// A shape was placed on the stack
+    Lock,
+    /// Rows were completed, with the number of rows
+    LineClear(usize),
+    /// A T shape was locked by a rotation into a slot with three of its
+    /// corners filled
+    TSpin,
+    /// A shape was put on hold
+    Hold,
+    /// The level went up
+    LevelUp,
+    /// The stack topped out
+    GameOver,
+}
+
+/// A game in progress. Boards hold `0` for empty cells and the
+/// [`ShapeType`] value of the shape that filled them otherwise.
+#[derive(Clone)]
 pub struct Game {
     board: [[u8; WIDTH]; HEIGHT],
+    /// Frame each cell of `board` was locked on. Empty for the active shape.
+    locked_at: [[Option<u64>; WIDTH]; HEIGHT],
+    /// Set after a line clear to briefly show a hidden stack
+    reveal_timer: Option<u64>,
     preview_board: [[u8; PSIZE]; PSIZE],
+    hold_board: [[u8; PSIZE]; PSIZE],
+    /// The shape being moved, or `None` between a lock and the next spawn
     pub active_shape: Option<Shape>,
     preview_shape: Option<Shape>,
+    hold_shape: Option<ShapeType>,
+    can_hold: bool,
+    /// Set once the stack tops out. The game takes no more input.
     pub game_over: bool,
-    fall_timer: Instant,
+    /// Frames played, not counting time spent paused
+    frame: u64,
+    fall_timer: u64,
+    lock_timer: u64,
+    /// Set while waiting to spawn the next shape (ARE and line clear delay)
+    entry_timer: Option<u64>,
+    /// Completed rows waiting for the line clear delay to pass
+    clearing: Vec<usize>,
+    /// Rows completed by the last line clear, with the cells they held
+    cleared: Vec<(usize, [u8; WIDTH])>,
+    /// Rotation and hold pressed during the entry delay (IRS and IHS)
+    initial_rotate: bool,
+    initial_hold: bool,
     next_shape: ShapeType,
+    seed: u64,
+    rng: Randomizer,
+    /// The mode, which decides the rules the game plays by
+    pub mode: Mode,
+    rotation: &'static dyn RotationSystem,
+    /// Points scored
     pub score: usize,
+    /// Lines cleared
+    pub lines: usize,
+    /// Shapes locked
+    pub pieces: usize,
+    /// While paused, frames don't advance and only `Action::Pause` is taken
     pub paused: bool,
+    /// Stats for the game so far
+    pub stats: Stats,
+    gravity: bool,
+    /// Whether the last successful move of the active shape was a rotation
+    last_rotate: bool,
+    events: Vec<Event>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Game {
+    /// Creates a marathon game with a random seed
     pub fn new() -> Self {
+        Game::with_mode(Mode::Marathon)
+    }
+
+    /// Creates a game in `mode` with a random seed
+    pub fn with_mode(mode: Mode) -> Self {
+        Game::with_seed(mode, rand::random())
+    }
+
+    /// Creates a game whose shape sequence is fully decided by `seed`
+    pub fn with_seed(mode: Mode, seed: u64) -> Self {
+        let mut rng = Randomizer::new(seed);
         Game {
             board: [[0; WIDTH]; HEIGHT],
+            locked_at: [[None; WIDTH]; HEIGHT],
+            reveal_timer: None,
             preview_board: [[0; PSIZE]; PSIZE],
+            hold_board: [[0; PSIZE]; PSIZE],
             active_shape: None,
             preview_shape: None,
+            hold_shape: None,
+            can_hold: true,
             game_over: false,
-            fall_timer: Instant::now(),
-            next_shape: ShapeType::random(),
+            frame: 0,
+            fall_timer: 0,
+            lock_timer: 0,
+            entry_timer: None,
+            clearing: Vec::new(),
+            cleared: Vec::new(),
+            initial_rotate: false,
+            initial_hold: false,
+            next_shape: rng.next_shape(),
+            seed,
+            rng,
+            mode,
+            rotation: mode.rotation(),
             score: 0,
+            lines: 0,
+            pieces: 0,
             paused: false,
+            stats: Stats::default(),
+            gravity: true,
+            last_rotate: false,
+            events: Vec::new(),
         }
     }
 
-    pub fn spawn(&mut self, shape_type: ShapeType, x: usize, y: isize) {
+    /// Replaces the active shape with a new one at (x, y)
+    pub fn spawn(&mut self, shape_type: ShapeType, x: isize, y: isize) {
         let shape = Shape::new(x, y, shape_type);
         self.active_shape = Some(shape);
     }
 
+    fn spawn_next(&mut self) {
+        let random_shape = self.rng.next_shape();
+        self.spawn(self.next_shape, (WIDTH / 2) as isize, -1);
+        self.next_shape = random_shape;
+    }
+
+    /// Advances the game by one frame
     pub fn update(&mut self) {
         if self.game_over || self.paused {
             return;
         }
+        self.frame += 1;
+        // Wait out the entry and line clear delays
+        if let Some(entry_timer) = self.entry_timer {
+            let mut delay = self.mode.entry_delay();
+            if !self.clearing.is_empty() {
+                delay += self.mode.line_clear_delay();
+            }
+            if self.frame - entry_timer < delay {
+                return;
+            }
+            self.entry_timer = None;
+            let rows = std::mem::take(&mut self.clearing);
+            self.collapse_rows(&rows);
+        }
         // Handle logic
         if self.active_shape.is_some() {
-            if self.fall_timer.elapsed().as_millis() > FALL_RATE {
-                self.fall_timer = Instant::now();
-                let old_coords = self
-                    .active_shape
-                    .as_ref()
-                    .unwrap()
-                    .to_coords(self.active_shape.as_ref().unwrap().dir);
+            if self.mode.twenty_g() {
+                if self.sonic_drop() {
+                    self.lock_timer = self.frame;
+                } else if self.frame - self.lock_timer > self.mode.lock_delay() {
+                    self.lock_shape();
+                    return;
+                }
+            } else if self.gravity && self.frame - self.fall_timer > FALL_RATE {
+                self.fall_timer = self.frame;
+                let old_y = self.active_shape.unwrap().y;
                 self.move_shape(Move::Down);
-                let new_coords = self
-                    .active_shape
-                    .as_ref()
-                    .unwrap()
-                    .to_coords(self.active_shape.as_ref().unwrap().dir);
                 // No longer falling
-                if old_coords == new_coords {
-                    self.shape_to_board();
-                    self.clear_completed();
-                } else {
-                    self.clear_coords(&old_coords);
+                if old_y == self.active_shape.unwrap().y {
+                    self.lock_shape();
+                    return;
                 }
             }
         } else {
             if self.board[0].iter().any(|&x| x != 0) {
-                self.game_over = true;
-                return;
+                if !self.mode.endless() {
+                    self.game_over = true;
+                    self.events.push(Event::GameOver);
+                    return;
+                }
+                self.board = [[0; WIDTH]; HEIGHT];
+                self.locked_at = [[None; WIDTH]; HEIGHT];
+            }
+            self.spawn_next();
+            if std::mem::take(&mut self.initial_hold) {
+                self.hold();
+            }
+            if std::mem::take(&mut self.initial_rotate) {
+                self.move_shape(Move::Rotate);
+            }
+            if self.mode.twenty_g() {
+                self.sonic_drop();
+                self.lock_timer = self.frame;
             }
-            let random_shape = ShapeType::random();
-            self.spawn(self.next_shape, WIDTH / 2, -1);
-            self.next_shape = random_shape;
         }
-        // Update shape position
-        if let Some(shape) = &mut self.active_shape {
-            let coords = shape.to_coords(shape.dir);
+        self.draw_active_shape();
+        // Update preview board
+        self.update_preview_board();
+    }
+
+    /// Applies a player input to the game
+    pub fn apply(&mut self, action: Action) {
+        if self.game_over || (self.paused && action != Action::Pause) {
+            return;
+        }
+        if let Action::Move(_) | Action::Drop | Action::Hold = action {
+            self.stats.keys += 1;
+        }
+        match action {
+            Action::Move(dir) => self.move_shape(dir),
+            Action::Drop => self.drop_shape(),
+            Action::Hold => self.hold(),
+            Action::Pause => self.paused = !self.paused,
+            Action::ToggleGravity => self.toggle_gravity(),
+            Action::ChooseNext(shape_type) => self.choose_next(shape_type),
+        }
+        if self.mode.twenty_g() && self.sonic_drop() {
+            self.lock_timer = self.frame;
+        }
+        self.draw_active_shape();
+    }
+
+    /// Writes the active shape's cells to the board
+    fn draw_active_shape(&mut self) {
+        if let Some(shape) = &self.active_shape {
+            let coords = self.rotation.coords(shape, shape.dir);
             for (x, y) in coords {
                 if x < 0 || x >= WIDTH as isize || y < 0 || y >= HEIGHT as isize {
                     continue;
@@ -93,15 +304,11 @@ impl Game {
                 self.board[y as usize][x as usize] = shape.shape_type as u8;
             }
         }
-        // Update preview board
-        self.update_preview_board();
     }
 
-    pub fn valid_move(
-        &mut self,
-        old_coords: &[(isize, isize)],
-        new_coords: &[(isize, isize)],
-    ) -> bool {
+    /// Whether the cells in `new_coords` are free, ignoring cells in
+    /// `old_coords` that the shape moving there occupies itself
+    pub fn valid_move(&self, old_coords: &[(isize, isize)], new_coords: &[(isize, isize)]) -> bool {
         for (xv, yv) in new_coords {
             let (x, y) = (*xv, *yv);
             if x < 0 || x >= WIDTH as isize || y >= HEIGHT as isize {
@@ -117,67 +324,94 @@ impl Game {
         true
     }
 
+    /// Moves or rotates the active shape if there is room for it
     pub fn move_shape(&mut self, dir: Move) {
         if self.game_over {
             return;
         }
         if self.active_shape.is_none() {
+            if dir == Move::Rotate && self.entry_timer.is_some() && self.mode.initial_actions() {
+                self.initial_rotate = true;
+            }
             return;
         }
         let mut shape = self.active_shape.unwrap();
-        let old_coords = shape.to_coords(shape.dir);
-        let mut new_coords = old_coords.clone();
-        match dir {
-            Move::Left => {
-                for (x, _) in &mut new_coords {
-                    *x -= 1;
-                }
-            }
-            Move::Right => {
-                for (x, _) in &mut new_coords {
-                    *x += 1;
-                }
-            }
-            Move::Down => {
-                for (_, y) in &mut new_coords {
-                    *y += 1;
-                }
-            }
+        let old_coords = self.rotation.coords(&shape, shape.dir);
+        let (new_dir, offsets) = match dir {
+            Move::Left => (shape.dir, vec![(-1, 0)]),
+            Move::Right => (shape.dir, vec![(1, 0)]),
+            Move::Down => (shape.dir, vec![(0, 1)]),
             Move::Rotate => {
                 let new_dir = shape.next_dir(shape.dir);
-                new_coords = shape.to_coords(new_dir);
+                let kicks = self.rotation.kicks(&shape, new_dir, &|cell| {
+                    !self.valid_move(&old_coords, &[cell])
+                });
+                (new_dir, kicks)
             }
-        }
-        if self.valid_move(&old_coords, &new_coords) {
-            match dir {
-                Move::Rotate => {
-                    shape.dir = shape.next_dir(shape.dir);
-                    self.clear_coords(&old_coords);
-                }
-                _ => {
-                    shape.x = new_coords[0].0 as usize;
-                    shape.y = new_coords[0].1 as isize;
-                    self.clear_coords(&old_coords);
+        };
+        let new_coords = self.rotation.coords(&shape, new_dir);
+        for (dx, dy) in offsets {
+            let moved = new_coords
+                .iter()
+                .map(|(x, y)| (x + dx, y + dy))
+                .collect::<Vec<(isize, isize)>>();
+            if self.valid_move(&old_coords, &moved) {
+                shape.dir = new_dir;
+                shape.x += dx;
+                shape.y += dy;
+                self.clear_coords(&old_coords);
+                self.last_rotate = dir == Move::Rotate;
+                match dir {
+                    Move::Left | Move::Right => self.events.push(Event::Move),
+                    Move::Rotate => self.events.push(Event::Rotate),
+                    Move::Down => {}
                 }
+                break;
             }
         }
         self.active_shape = Some(shape);
     }
 
+    /// Leaves the active shape on the board as locked cells
     pub fn shape_to_board(&mut self) {
         if self.active_shape.is_none() {
             return;
         }
         let shape = self.active_shape.take().unwrap();
-        let coords = shape.to_coords(shape.dir);
+        let coords = self.rotation.coords(&shape, shape.dir);
         for (x, y) in coords {
             if x < 0 || x >= WIDTH as isize || y < 0 || y >= HEIGHT as isize {
                 continue;
             }
             self.board[y as usize][x as usize] = shape.shape_type as u8;
+            self.locked_at[y as usize][x as usize] = Some(self.frame);
+        }
+    }
+
+    /// Places the active shape on the board, clears any completed rows and
+    /// starts the entry delay for the next shape.
+    fn lock_shape(&mut self) {
+        let Some(shape) = self.active_shape else {
+            return;
+        };
+        self.pieces += 1;
+        self.events.push(Event::Lock);
+        let t_spin = self.is_t_spin();
+        if t_spin {
+            self.events.push(Event::TSpin);
+        }
+        self.shape_to_board();
+        let lines = self.lines;
+        self.clear_completed();
+        self.stats
+            .record_lock(shape.shape_type, self.lines - lines, t_spin);
+        self.can_hold = true;
+        if self.mode.entry_delay() > 0 || !self.clearing.is_empty() {
+            self.entry_timer = Some(self.frame);
         }
     }
 
+    /// Empties the given board cells
     pub fn clear_coords(&mut self, coords: &Vec<(isize, isize)>) {
         for (xv, yv) in coords {
             let (x, y) = (*xv, *yv);
@@ -185,9 +419,11 @@ impl Game {
                 continue;
             }
             self.board[y as usize][x as usize] = 0;
+            self.locked_at[y as usize][x as usize] = None;
         }
     }
 
+    /// Scores and clears any full rows
     pub fn clear_completed(&mut self) {
         let mut completed = Vec::new();
         for (y, row) in self.board.iter().enumerate() {
@@ -203,43 +439,126 @@ impl Game {
             4 => self.score += 2000,
             _ => self.score += 3200,
         }
-        for y in completed.clone() {
+        let level = self.level();
+        self.lines += completed.len();
+        if !completed.is_empty() {
+            self.cleared = completed.iter().map(|&y| (y, self.board[y])).collect();
+            self.reveal_timer = Some(self.frame);
+            self.events.push(Event::LineClear(completed.len()));
+        }
+        if self.level() > level {
+            self.events.push(Event::LevelUp);
+        }
+        if self.mode.line_clear_delay() > 0 {
+            // Rows stay on the board until the line clear delay has passed
+            self.clearing = completed;
+        } else {
+            self.collapse_rows(&completed);
+        }
+    }
+
+    fn collapse_rows(&mut self, rows: &[usize]) {
+        for &y in rows {
             for x in 0..WIDTH {
                 self.board[y][x] = 0;
+                self.locked_at[y][x] = None;
             }
         }
-        for y in completed {
+        for &y in rows {
             for y2 in (0..y).rev() {
                 for x in 0..WIDTH {
                     self.board[y2 + 1][x] = self.board[y2][x];
+                    self.locked_at[y2 + 1][x] = self.locked_at[y2][x];
                 }
             }
         }
     }
 
+    /// Whether the active shape is a T that got into place by rotating and
+    /// has at least three of the four cells diagonal to its centre filled
+    fn is_t_spin(&self) -> bool {
+        let Some(shape) = self.active_shape else {
+            return false;
+        };
+        if shape.shape_type != ShapeType::T || !self.last_rotate {
+            return false;
+        }
+        let coords = self.rotation.coords(&shape, shape.dir);
+        // The centre is the cell next to all three others
+        let Some(&(cx, cy)) = coords.iter().find(|&&(x, y)| {
+            coords
+                .iter()
+                .filter(|&&(x2, y2)| (x - x2).abs() + (y - y2).abs() == 1)
+                .count()
+                == 3
+        }) else {
+            return false;
+        };
+        [(-1, -1), (1, -1), (-1, 1), (1, 1)]
+            .iter()
+            .filter(|(dx, dy)| {
+                let (x, y) = (cx + dx, cy + dy);
+                x < 0
+                    || x >= WIDTH as isize
+                    || y >= HEIGHT as isize
+                    || (y >= 0 && self.board[y as usize][x as usize] != 0)
+            })
+            .count()
+            >= 3
+    }
+
+    /// Moves the active shape down as far as it goes without locking it.
+    /// Returns true if the shape moved.
+    fn sonic_drop(&mut self) -> bool {
+        let Some(start_y) = self.active_shape.map(|shape| shape.y) else {
+            return false;
+        };
+        loop {
+            let y = self.active_shape.unwrap().y;
+            self.move_shape(Move::Down);
+            if self.active_shape.unwrap().y == y {
+                break;
+            }
+        }
+        self.active_shape.unwrap().y != start_y
+    }
+
+    /// Drops the active shape as far as it goes and locks it
     pub fn drop_shape(&mut self) {
         if self.active_shape.is_none() {
             return;
         }
-        let mut shape = self.active_shape.take().unwrap();
-        let old_coords = shape.to_coords(shape.dir);
-        loop {
-            let mut new_coords = shape.to_coords(shape.dir);
-            for (_, y) in &mut new_coords {
-                *y += 1;
-            }
-            if self.valid_move(&old_coords, &new_coords) {
-                shape.y += 1;
-            } else {
-                break;
+        self.sonic_drop();
+        self.lock_shape();
+    }
+
+    /// Swaps the active shape with the held one, or with the next shape if
+    /// nothing is held yet. Only one hold is allowed per locked shape.
+    pub fn hold(&mut self) {
+        if self.game_over || self.paused {
+            return;
+        }
+        if self.active_shape.is_none() {
+            if self.entry_timer.is_some() && self.mode.initial_actions() {
+                self.initial_hold = true;
             }
+            return;
         }
-        self.active_shape = Some(shape);
-        self.clear_coords(&old_coords);
-        self.shape_to_board();
-        self.clear_completed();
+        if !self.can_hold {
+            return;
+        }
+        let shape = self.active_shape.take().unwrap();
+        self.clear_coords(&self.rotation.coords(&shape, shape.dir));
+        self.can_hold = false;
+        self.events.push(Event::Hold);
+        match self.hold_shape.replace(shape.shape_type) {
+            Some(shape_type) => self.spawn(shape_type, (WIDTH / 2) as isize, -1),
+            None => self.spawn_next(),
+        }
+        fill_preview(&mut self.hold_board, shape.shape_type);
     }
 
+    /// Redraws the next shape preview if the next shape changed
     pub fn update_preview_board(&mut self) {
         // Only update if preview shape has changed
         if !(self.preview_shape.is_none()
@@ -247,64 +566,178 @@ impl Game {
         {
             return;
         }
-        for y in 0..PSIZE {
-            for x in 0..PSIZE {
-                self.preview_board[y][x] = 0;
-            }
+        fill_preview(&mut self.preview_board, self.next_shape);
+    }
+
+    /// Switches gravity on or off in zen mode
+    pub fn toggle_gravity(&mut self) {
+        if self.mode.endless() {
+            self.gravity = !self.gravity;
         }
-        match self.next_shape {
-            ShapeType::I => {
-                self.preview_board[3][0] = ShapeType::I as u8;
-                self.preview_board[3][1] = ShapeType::I as u8;
-                self.preview_board[3][2] = ShapeType::I as u8;
-                self.preview_board[3][3] = ShapeType::I as u8;
-            }
-            ShapeType::J => {
-                self.preview_board[0][0] = ShapeType::J as u8;
-                self.preview_board[1][0] = ShapeType::J as u8;
-                self.preview_board[1][1] = ShapeType::J as u8;
-                self.preview_board[1][2] = ShapeType::J as u8;
-            }
-            ShapeType::L => {
-                self.preview_board[1][0] = ShapeType::L as u8;
-                self.preview_board[1][1] = ShapeType::L as u8;
-                self.preview_board[1][2] = ShapeType::L as u8;
-                self.preview_board[2][0] = ShapeType::L as u8;
-            }
-            ShapeType::O => {
-                self.preview_board[0][1] = ShapeType::O as u8;
-                self.preview_board[0][2] = ShapeType::O as u8;
-                self.preview_board[1][1] = ShapeType::O as u8;
-                self.preview_board[1][2] = ShapeType::O as u8;
-            }
-            ShapeType::S => {
-                self.preview_board[0][1] = ShapeType::S as u8;
-                self.preview_board[1][1] = ShapeType::S as u8;
-                self.preview_board[1][2] = ShapeType::S as u8;
-                self.preview_board[2][2] = ShapeType::S as u8;
-            }
-            ShapeType::T => {
-                self.preview_board[0][1] = ShapeType::T as u8;
-                self.preview_board[1][1] = ShapeType::T as u8;
-                self.preview_board[1][2] = ShapeType::T as u8;
-                self.preview_board[2][1] = ShapeType::T as u8;
-            }
-            ShapeType::Z => {
-                self.preview_board[1][0] = ShapeType::Z as u8;
-                self.preview_board[1][1] = ShapeType::Z as u8;
-                self.preview_board[2][1] = ShapeType::Z as u8;
-                self.preview_board[2][2] = ShapeType::Z as u8;
+    }
+
+    /// Whether shapes fall on their own
+    pub fn gravity(&self) -> bool {
+        self.gravity
+    }
+
+    /// Picks the next shape in zen mode instead of a random one
+    pub fn choose_next(&mut self, shape_type: ShapeType) {
+        if self.mode.endless() {
+            self.next_shape = shape_type;
+            self.update_preview_board();
+        }
+    }
+
+    /// Starts at 1 and goes up every `LINES_PER_LEVEL` lines
+    pub fn level(&self) -> usize {
+        self.lines / LINES_PER_LEVEL + 1
+    }
+
+    /// Number of rows from the floor up to the highest locked cell
+    pub fn stack_height(&self) -> usize {
+        self.locked_at
+            .iter()
+            .position(|row| row.iter().any(Option::is_some))
+            .map_or(0, |y| HEIGHT - y)
+    }
+
+    /// Returns the events since the last call. Events pile up until they
+    /// are taken.
+    pub fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Returns the grade for modes that award one
+    pub fn grade(&self) -> Option<&'static str> {
+        self.mode.grade(self.score, self.play_time())
+    }
+
+    /// The seed the shape sequence was generated from
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Frames played, not counting time spent paused
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Pieces locked per second played
+    pub fn pieces_per_second(&self) -> f64 {
+        per(self.pieces, self.play_time().as_secs_f64())
+    }
+
+    /// Inputs per piece locked
+    pub fn keys_per_piece(&self) -> f64 {
+        per(self.stats.keys, self.pieces as f64)
+    }
+
+    /// Garbage lines sent per minute played
+    pub fn attack_per_minute(&self) -> f64 {
+        per(self.stats.attack, self.play_time().as_secs_f64() / 60.0)
+    }
+
+    /// Completed rows that are still on the board, waiting for the line
+    /// clear delay to pass
+    pub fn clearing(&self) -> &[usize] {
+        &self.clearing
+    }
+
+    /// Rows completed by the last line clear and the cells they held, for
+    /// front ends to animate in modes where they're removed straight away
+    pub fn cleared_rows(&self) -> &[(usize, [u8; WIDTH])] {
+        &self.cleared
+    }
+
+    /// How far through the line clear delay the game is, from 0 to 1, or
+    /// `None` when no rows are being cleared
+    pub fn clear_progress(&self) -> Option<f64> {
+        let entry_timer = self.entry_timer.filter(|_| !self.clearing.is_empty())?;
+        let delay = self.mode.entry_delay() + self.mode.line_clear_delay();
+        Some((self.frame - entry_timer) as f64 / delay as f64)
+    }
+
+    /// Frames since the cell at (x, y) was locked, or `None` if it's empty
+    /// or part of the active shape
+    pub fn lock_age(&self, x: usize, y: usize) -> Option<u64> {
+        self.locked_at[y][x].map(|locked_at| self.frame - locked_at)
+    }
+
+    /// Time played, not counting pauses
+    pub fn play_time(&self) -> Duration {
+        Duration::from_millis(self.frame * 1000 / FPS)
+    }
+
+    /// Whether the cell at (x, y) should be drawn. Locked cells are hidden
+    /// in modes with a fading stack, except right after a line clear and
+    /// once the game is over.
+    pub fn cell_visible(&self, x: usize, y: usize) -> bool {
+        let (Some(fade), Some(locked_at)) = (self.mode.stack_fade(), self.locked_at[y][x]) else {
+            return true;
+        };
+        if self.game_over {
+            return true;
+        }
+        if let Some(reveal_timer) = self.reveal_timer {
+            if self.frame - reveal_timer < REVEAL_TIME {
+                return true;
             }
         }
+        self.frame - locked_at < fade
+    }
+
+    /// FNV-1a hash of the board cells, stable across platforms and builds
+    pub fn board_hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for &cell in self.board.iter().flatten() {
+            hash ^= cell as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        hash
     }
 
+    /// The board including the active shape, indexed `[y][x]` from the top
     pub fn board_ref(&self) -> &[[u8; WIDTH]; HEIGHT] {
         &self.board
     }
 
+    /// The next shape
     pub fn preview_board_ref(&self) -> &[[u8; PSIZE]; PSIZE] {
         &self.preview_board
     }
+
+    /// The held shape, empty if nothing is held
+    pub fn hold_board_ref(&self) -> &[[u8; PSIZE]; PSIZE] {
+        &self.hold_board
+    }
+}
+
+/// `count / amount`, or 0 before there is any amount to divide by
+fn per(count: usize, amount: f64) -> f64 {
+    if amount > 0.0 {
+        count as f64 / amount
+    } else {
+        0.0
+    }
+}
+
+fn fill_preview(preview_board: &mut [[u8; PSIZE]; PSIZE], shape_type: ShapeType) {
+    for row in preview_board.iter_mut() {
+        row.fill(0);
+    }
+    let cells = match shape_type {
+        ShapeType::I => [(3, 0), (3, 1), (3, 2), (3, 3)],
+        ShapeType::J => [(0, 0), (1, 0), (1, 1), (1, 2)],
+        ShapeType::L => [(1, 0), (1, 1), (1, 2), (2, 0)],
+        ShapeType::O => [(0, 1), (0, 2), (1, 1), (1, 2)],
+        ShapeType::S => [(0, 1), (1, 1), (1, 2), (2, 2)],
+        ShapeType::T => [(0, 1), (1, 1), (1, 2), (2, 1)],
+        ShapeType::Z => [(1, 0), (1, 1), (2, 1), (2, 2)],
+    };
+    for (y, x) in cells {
+        preview_board[y][x] = shape_type as u8;
+    }
 }
 
 #[cfg(test)]
@@ -397,4 +830,181 @@ mod tests {
             .to_coords(game.active_shape.unwrap().dir);
         assert_eq!(coords, vec![(3, 0), (3, -3), (3, -2), (3, -1)]);
     }
+
+    #[test]
+    fn test_twenty_g() {
+        let mut game = Game::with_mode(Mode::Master);
+        game.update();
+        let shape = game.active_shape.unwrap();
+        let coords = game.rotation.coords(&shape, shape.dir);
+        assert_eq!(
+            coords.iter().map(|(_, y)| *y).max(),
+            Some(HEIGHT as isize - 1)
+        );
+    }
+
+    #[test]
+    fn test_hold() {
+        let mut game = Game::new();
+        game.spawn(ShapeType::T, 3, 0);
+        game.hold();
+        assert_eq!(game.hold_shape, Some(ShapeType::T));
+        let held = game.active_shape.unwrap().shape_type;
+        // Only one hold per shape
+        game.hold();
+        assert_eq!(game.active_shape.unwrap().shape_type, held);
+        assert_eq!(game.hold_shape, Some(ShapeType::T));
+    }
+
+    #[test]
+    fn test_cell_visible() {
+        let mut game = Game::with_mode(Mode::Invisible);
+        game.spawn(ShapeType::O, 3, 0);
+        game.drop_shape();
+        assert_ne!(game.board[HEIGHT - 1][3], 0);
+        assert!(!game.cell_visible(3, HEIGHT - 1));
+        assert!(game.cell_visible(0, HEIGHT - 1));
+        game.game_over = true;
+        assert!(game.cell_visible(3, HEIGHT - 1));
+    }
+
+    #[test]
+    fn test_zen_top_out() {
+        let mut game = Game::with_mode(Mode::Zen);
+        game.board[0][0] = 1;
+        game.update();
+        assert!(!game.game_over);
+        assert_eq!(game.board[0][0], 0);
+    }
+
+    #[test]
+    fn test_events() {
+        let mut game = Game::new();
+        game.spawn(ShapeType::I, 3, 0);
+        game.move_shape(Move::Left);
+        game.move_shape(Move::Down);
+        game.move_shape(Move::Rotate);
+        game.hold();
+        game.drop_shape();
+        assert_eq!(
+            game.take_events(),
+            vec![Event::Move, Event::Rotate, Event::Hold, Event::Lock]
+        );
+        assert!(game.take_events().is_empty());
+    }
+
+    #[test]
+    fn test_line_clear_events() {
+        let mut game = Game::new();
+        game.lines = LINES_PER_LEVEL - 1;
+        game.board[HEIGHT - 1] = [1; WIDTH];
+        game.board[HEIGHT - 1][2..6].fill(0);
+        game.spawn(ShapeType::I, 3, 0);
+        game.drop_shape();
+        assert_eq!(
+            game.take_events(),
+            vec![Event::Lock, Event::LineClear(1), Event::LevelUp]
+        );
+        assert_eq!(game.level(), 2);
+    }
+
+    #[test]
+    fn test_line_clear_delay() {
+        let mut game = Game::with_mode(Mode::Master);
+        game.board[HEIGHT - 1] = [1; WIDTH];
+        game.board[HEIGHT - 1][2..6].fill(0);
+        game.spawn(ShapeType::I, 3, 0);
+        assert_eq!(game.clear_progress(), None);
+        game.drop_shape();
+        assert_eq!(game.clearing(), [HEIGHT - 1]);
+        assert_eq!(game.clear_progress(), Some(0.0));
+        assert_eq!(game.lock_age(2, HEIGHT - 1), Some(0));
+        let delay = Mode::Master.entry_delay() + Mode::Master.line_clear_delay();
+        for _ in 0..delay - 1 {
+            game.update();
+        }
+        assert_eq!(
+            game.clear_progress(),
+            Some((delay - 1) as f64 / delay as f64)
+        );
+        assert!(game.active_shape.is_none());
+        game.update();
+        assert!(game.clearing().is_empty());
+        assert!(game.active_shape.is_some());
+        // Only the new shape is left, dropped to the floor
+        assert_eq!(game.board.iter().flatten().filter(|&&c| c != 0).count(), 4);
+    }
+
+    #[test]
+    fn test_line_clear_without_delay() {
+        let mut game = Game::new();
+        game.board[HEIGHT - 1] = [1; WIDTH];
+        game.board[HEIGHT - 1][2..6].fill(0);
+        game.spawn(ShapeType::I, 3, 0);
+        game.drop_shape();
+        // The rows go at once and the next shape spawns on the next frame
+        assert!(game.clearing().is_empty());
+        assert_eq!(game.clear_progress(), None);
+        assert_eq!(game.board[HEIGHT - 1], [0; WIDTH]);
+        let mut cleared = [1; WIDTH];
+        cleared[2..6].fill(ShapeType::I as u8);
+        assert_eq!(game.cleared_rows(), [(HEIGHT - 1, cleared)]);
+        game.update();
+        assert!(game.active_shape.is_some());
+    }
+
+    #[test]
+    fn test_t_spin() {
+        let mut game = Game::new();
+        game.spawn(ShapeType::T, 5, HEIGHT as isize - 2);
+        game.board[HEIGHT - 3][4] = 1;
+        game.board[HEIGHT - 1][4] = 1;
+        game.board[HEIGHT - 1][6] = 1;
+        assert!(!game.is_t_spin());
+        game.last_rotate = true;
+        game.drop_shape();
+        assert_eq!(game.take_events(), vec![Event::Lock, Event::TSpin]);
+    }
+
+    #[test]
+    fn test_stack_height() {
+        let mut game = Game::new();
+        assert_eq!(game.stack_height(), 0);
+        game.spawn(ShapeType::I, 3, 0);
+        game.move_shape(Move::Rotate);
+        game.drop_shape();
+        assert_eq!(game.stack_height(), 4);
+        // The falling shape isn't part of the stack
+        game.spawn(ShapeType::O, 8, 0);
+        game.update();
+        assert_eq!(game.stack_height(), 4);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut game = Game::new();
+        assert_eq!(game.pieces_per_second(), 0.0);
+        assert_eq!(game.keys_per_piece(), 0.0);
+        game.update();
+        game.apply(Action::Move(Move::Left));
+        game.apply(Action::Move(Move::Rotate));
+        game.apply(Action::Drop);
+        game.apply(Action::Pause);
+        assert_eq!(game.stats.keys, 3);
+        assert_eq!(game.pieces, 1);
+        assert_eq!(game.stats.distribution.iter().sum::<usize>(), 1);
+        assert_eq!(game.keys_per_piece(), 3.0);
+        assert_eq!(
+            game.pieces_per_second(),
+            1.0 / game.play_time().as_secs_f64()
+        );
+    }
+
+    #[test]
+    fn test_board_hash() {
+        let mut game = Game::new();
+        assert_eq!(game.board_hash(), 0xe7f6c4b09523c5e5);
+        game.board[HEIGHT - 1][0] = ShapeType::T as u8;
+        assert_ne!(game.board_hash(), 0xe7f6c4b09523c5e5);
+    }
 }
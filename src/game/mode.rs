@@ -0,0 +1,182 @@
+//! Game modes and the rules each plays by.
+
+use super::rotation::{RotationSystem, ARS, CLASSIC};
+use std::time::Duration;
+
+/// Score needed for each master mode grade, lowest first.
+const GRADES: [(&str, usize); 18] = [
+    ("9", 0),
+    ("8", 4000),
+    ("7", 8000),
+    ("6", 14000),
+    ("5", 20000),
+    ("4", 35000),
+    ("3", 55000),
+    ("2", 80000),
+    ("1", 120000),
+    ("S1", 160000),
+    ("S2", 220000),
+    ("S3", 300000),
+    ("S4", 400000),
+    ("S5", 520000),
+    ("S6", 660000),
+    ("S7", 820000),
+    ("S8", 1000000),
+    ("S9", 1200000),
+];
+/// Reaching S9 within this time awards the Grand Master grade.
+const GM_TIME: Duration = Duration::from_secs(13 * 60 + 30);
+
+/// A way to play, with its own rules
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Mode {
+    /// The default mode
+    Marathon,
+    /// TGM-style mode with 20G gravity, ARS rotation, entry and line clear
+    /// delays and grades.
+    Master,
+    /// Locked cells are hidden as soon as they land
+    Invisible,
+    /// Locked cells fade out a few seconds after they land
+    Fading,
+    /// Sandbox without top-outs, with optional gravity, a choosable next
+    /// shape and undo.
+    Zen,
+}
+
+impl Mode {
+    /// The name the mode is started with and saved under
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Marathon => "marathon",
+            Mode::Master => "master",
+            Mode::Invisible => "invisible",
+            Mode::Fading => "fading",
+            Mode::Zen => "zen",
+        }
+    }
+
+    /// The mode called `name`, if there is one
+    pub fn from_name(name: &str) -> Option<Mode> {
+        match name {
+            "marathon" => Some(Mode::Marathon),
+            "master" => Some(Mode::Master),
+            "invisible" => Some(Mode::Invisible),
+            "fading" => Some(Mode::Fading),
+            "zen" => Some(Mode::Zen),
+            _ => None,
+        }
+    }
+
+    /// The rotation system shapes turn by
+    pub fn rotation(&self) -> &'static dyn RotationSystem {
+        match self {
+            Mode::Master => &ARS,
+            _ => &CLASSIC,
+        }
+    }
+
+    /// Pieces drop to the bottom of the board as soon as they spawn or move
+    pub fn twenty_g(&self) -> bool {
+        *self == Mode::Master
+    }
+
+    /// Frames between a piece locking and the next one spawning (ARE)
+    pub fn entry_delay(&self) -> u64 {
+        match self {
+            Mode::Master => 30,
+            _ => 0,
+        }
+    }
+
+    /// Extra frames before cleared lines are removed from the board
+    pub fn line_clear_delay(&self) -> u64 {
+        match self {
+            Mode::Master => 41,
+            _ => 0,
+        }
+    }
+
+    /// Rotating or holding before a piece spawns applies to it as it spawns
+    /// (IRS and IHS)
+    pub fn initial_actions(&self) -> bool {
+        *self == Mode::Master
+    }
+
+    /// Frames a grounded piece can still be moved before it locks
+    pub fn lock_delay(&self) -> u64 {
+        match self {
+            Mode::Master => 30,
+            _ => 0,
+        }
+    }
+
+    /// Frames after locking before a cell on the stack is hidden, or `None`
+    /// if the stack is always visible.
+    pub fn stack_fade(&self) -> Option<u64> {
+        match self {
+            Mode::Invisible => Some(0),
+            Mode::Fading => Some(180),
+            _ => None,
+        }
+    }
+
+    /// Topping out clears the board instead of ending the game
+    pub fn endless(&self) -> bool {
+        *self == Mode::Zen
+    }
+
+    /// The rules the mode plays by, as comma separated `key value` pairs.
+    /// Replays record them so one recorded under other rules is refused
+    /// rather than played back wrong.
+    pub fn rules(&self) -> String {
+        format!(
+            "rotation {}, twenty_g {}, entry_delay {}, line_clear_delay {}, lock_delay {}, \
+             initial_actions {}, stack_fade {}, endless {}",
+            self.rotation().name(),
+            self.twenty_g() as u8,
+            self.entry_delay(),
+            self.line_clear_delay(),
+            self.lock_delay(),
+            self.initial_actions() as u8,
+            self.stack_fade()
+                .map_or("-".to_string(), |fade| fade.to_string()),
+            self.endless() as u8
+        )
+    }
+
+    /// Returns the grade for the given score and play time, or `None` for
+    /// modes without grades.
+    pub fn grade(&self, score: usize, play_time: Duration) -> Option<&'static str> {
+        if *self != Mode::Master {
+            return None;
+        }
+        let (grade, _) = GRADES
+            .iter()
+            .rev()
+            .find(|(_, min_score)| score >= *min_score)
+            .unwrap();
+        if *grade == "S9" && play_time <= GM_TIME {
+            return Some("GM");
+        }
+        Some(grade)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grade() {
+        let time = Duration::from_secs(60);
+        assert_eq!(Mode::Marathon.grade(5000, time), None);
+        assert_eq!(Mode::Master.grade(0, time), Some("9"));
+        assert_eq!(Mode::Master.grade(8500, time), Some("7"));
+        assert_eq!(Mode::Master.grade(1200000, time), Some("GM"));
+        assert_eq!(
+            Mode::Master.grade(1200000, Duration::from_secs(15 * 60)),
+            Some("S9")
+        );
+    }
+}
@@ -0,0 +1,170 @@
+//! Music playlists read from a directory.
+
+use super::randomizer::Randomizer;
+use std::path::{Path, PathBuf};
+
+/// Audio files picked up from a music directory
+const MUSIC_EXTENSIONS: [&str; 4] = ["mp3", "ogg", "wav", "flac"];
+
+/// The order music tracks are played in
+pub struct Playlist {
+    tracks: Vec<PathBuf>,
+    /// Indexes into `tracks` in play order
+    order: Vec<usize>,
+    position: usize,
+    shuffle: bool,
+    /// Start over once the last track has played
+    pub repeat: bool,
+    rng: Randomizer,
+}
+
+impl Playlist {
+    /// Makes a playlist of `tracks`, in order unless `shuffle` is set, with
+    /// `seed` deciding the shuffled orders
+    pub fn new(tracks: Vec<PathBuf>, shuffle: bool, repeat: bool, seed: u64) -> Self {
+        let mut playlist = Playlist {
+            order: (0..tracks.len()).collect(),
+            tracks,
+            position: 0,
+            shuffle,
+            repeat,
+            rng: Randomizer::new(seed),
+        };
+        playlist.reorder();
+        playlist
+    }
+
+    /// Makes a playlist of the audio files in `dir`, sorted by name. A
+    /// missing directory gives an empty playlist.
+    pub fn from_dir(dir: &Path, shuffle: bool, repeat: bool, seed: u64) -> Self {
+        let mut tracks = std::fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| {
+                        path.extension()
+                            .and_then(|ext| ext.to_str())
+                            .is_some_and(|ext| {
+                                MUSIC_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+                            })
+                    })
+                    .collect::<Vec<PathBuf>>()
+            })
+            .unwrap_or_default();
+        tracks.sort();
+        Playlist::new(tracks, shuffle, repeat, seed)
+    }
+
+    /// Whether there are no tracks to play
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    /// Number of tracks
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// The track playing, or `None` if there are no tracks
+    pub fn current(&self) -> Option<&Path> {
+        self.order
+            .get(self.position)
+            .map(|&i| self.tracks[i].as_path())
+    }
+
+    /// Moves on to the next track. Returns `None` after the last track
+    /// unless the playlist repeats.
+    pub fn next_track(&mut self) -> Option<&Path> {
+        if self.position + 1 < self.order.len() {
+            self.position += 1;
+        } else if self.repeat {
+            self.reorder();
+        } else {
+            return None;
+        }
+        self.current()
+    }
+
+    /// Moves back a track, wrapping around to the last one
+    pub fn previous_track(&mut self) -> Option<&Path> {
+        if self.order.is_empty() {
+            return None;
+        }
+        self.position = (self.position + self.order.len() - 1) % self.order.len();
+        self.current()
+    }
+
+    /// Starts the play order over, reshuffling it if shuffle is on
+    fn reorder(&mut self) {
+        self.position = 0;
+        if !self.shuffle {
+            return;
+        }
+        // Fisher-Yates
+        for i in (1..self.order.len()).rev() {
+            let j = (self.rng.next_u64() % (i as u64 + 1)) as usize;
+            self.order.swap(i, j);
+        }
+    }
+}
+
+/// The name a track is shown with: its file name without the extension
+pub fn track_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracks(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn test_in_order() {
+        let mut playlist = Playlist::new(tracks(&["a", "b", "c"]), false, false, 1);
+        assert_eq!(playlist.current(), Some(Path::new("a")));
+        assert_eq!(playlist.next_track(), Some(Path::new("b")));
+        assert_eq!(playlist.next_track(), Some(Path::new("c")));
+        assert_eq!(playlist.next_track(), None);
+        assert_eq!(playlist.previous_track(), Some(Path::new("b")));
+        playlist.repeat = true;
+        playlist.next_track();
+        assert_eq!(playlist.next_track(), Some(Path::new("a")));
+        assert_eq!(playlist.previous_track(), Some(Path::new("c")));
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let names = ["a", "b", "c", "d", "e", "f", "g", "h"];
+        let mut playlist = Playlist::new(tracks(&names), true, true, 7);
+        let mut played = vec![playlist.current().unwrap().to_path_buf()];
+        for _ in 1..names.len() {
+            played.push(playlist.next_track().unwrap().to_path_buf());
+        }
+        // Every track plays once per round
+        played.sort();
+        assert_eq!(played, tracks(&names));
+        assert!(playlist.next_track().is_some());
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut playlist = Playlist::from_dir(Path::new("no such dir"), true, true, 1);
+        assert!(playlist.is_empty());
+        assert_eq!(playlist.current(), None);
+        assert_eq!(playlist.next_track(), None);
+        assert_eq!(playlist.previous_track(), None);
+    }
+
+    #[test]
+    fn test_track_name() {
+        assert_eq!(
+            track_name(Path::new("music/Korobeiniki.ogg")),
+            "Korobeiniki"
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! The seeded shape sequence.
+
+use super::shapes::ShapeType;
+
+/// Seeded xorshift64* generator for the shape sequence. The whole state is
+/// a single `u64`, so it can be cloned along with the game and saved.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Randomizer {
+    state: u64,
+}
+
+impl Randomizer {
+    /// A generator starting from `seed`, or from a saved `state`
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck on a zero state
+        Randomizer { state: seed.max(1) }
+    }
+
+    /// The state to save, which `Randomizer::new` carries on from
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// The next random number
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// The next shape in the sequence, each equally likely
+    pub fn next_shape(&mut self) -> ShapeType {
+        ShapeType::from_u8((self.next_u64() % 7) as u8 + 1).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_shapes() {
+        let mut a = Randomizer::new(42);
+        let mut b = Randomizer::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_shape(), b.next_shape());
+        }
+    }
+
+    #[test]
+    fn test_zero_seed() {
+        let mut rng = Randomizer::new(0);
+        assert_ne!(rng.next_u64(), 0);
+    }
+}
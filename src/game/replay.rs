@@ -0,0 +1,604 @@
+//! Recording, saving and playing back games.
+
+use super::mode::Mode;
+use super::shapes::ShapeType;
+use super::{Action, Game, Move, FPS};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Bumped whenever the replay format changes
+pub const REPLAY_VERSION: u8 = 4;
+const MAGIC: &[u8; 4] = b"TRPL";
+/// Header flag for a deflate compressed body
+const COMPRESSED: u8 = 1;
+/// Frames between the copies of the game kept for seeking backwards
+const KEYFRAME_INTERVAL: u64 = 5 * FPS;
+/// Most frames a game can go on for after its last action. Without input
+/// the stack tops out long before this.
+const MAX_TAIL: u64 = 30 * 60 * FPS;
+
+/// Why a replay couldn't be read or played back
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The replay file couldn't be read or written
+    Io(std::io::Error),
+    /// The replay was written by a different format version
+    Version(u8),
+    /// The replay couldn't be decoded, with the field that was bad
+    Corrupt(&'static str),
+    /// The replay was recorded under different rules than its mode has now
+    Rules(String),
+    /// Playing the replay back gave a different result than was recorded
+    Divergence {
+        /// What differs: score, lines, frames or board
+        field: &'static str,
+        /// The recorded value
+        expected: u64,
+        /// The value played back
+        actual: u64,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "could not read replay: {}", e),
+            ReplayError::Version(v) => write!(
+                f,
+                "replay has format version {}, expected {}",
+                v, REPLAY_VERSION
+            ),
+            ReplayError::Corrupt(field) => write!(f, "replay is corrupt ({})", field),
+            ReplayError::Rules(rules) => write!(f, "replay was recorded with rules {}", rules),
+            ReplayError::Divergence {
+                field,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "replay diverged: recorded {} {}, played back {}",
+                field, expected, actual
+            ),
+        }
+    }
+}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(e: std::io::Error) -> Self {
+        ReplayError::Io(e)
+    }
+}
+
+/// Everything needed to play a game again: the seed and mode it started
+/// with and every action along with the frame it was applied on.
+///
+/// Settings that change play, such as the rotation system, IRS/IHS and the
+/// delays, come from the mode. They are recorded with it as `rules`, and
+/// replays whose rules differ from the mode's are refused.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Replay {
+    /// Seed of the shape sequence
+    pub seed: u64,
+    /// Mode the game was played in
+    pub mode: Mode,
+    /// `Mode::rules` when the game was recorded
+    pub rules: String,
+    /// Every action, with the frame it was applied on, in order
+    pub actions: Vec<(u64, Action)>,
+    /// Score the recorded game ended with
+    pub score: usize,
+    /// Lines the recorded game ended with
+    pub lines: usize,
+    /// Frames the recorded game lasted
+    pub frames: u64,
+    /// `Game::board_hash` of the final board
+    pub board_hash: u64,
+}
+
+impl Replay {
+    /// Starts recording a game that hasn't been played yet
+    pub fn new(game: &Game) -> Self {
+        Replay {
+            seed: game.seed(),
+            mode: game.mode,
+            rules: game.mode.rules(),
+            actions: Vec::new(),
+            score: 0,
+            lines: 0,
+            frames: 0,
+            board_hash: 0,
+        }
+    }
+
+    /// Adds an action applied on `frame`
+    pub fn record(&mut self, frame: u64, action: Action) {
+        self.actions.push((frame, action));
+    }
+
+    /// Stores the result of the recorded game
+    pub fn finish(&mut self, game: &Game) {
+        self.score = game.score;
+        self.lines = game.lines;
+        self.frames = game.frame();
+        self.board_hash = game.board_hash();
+    }
+
+    /// Writes the replay to `path`, creating its directory if needed
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Reads a replay written by `Replay::save`
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        Replay::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Encodes the replay as a header followed by a body of varints, with
+    /// action frames stored as the difference from the previous action.
+    /// The body is compressed when that makes it smaller.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        write_varint(&mut body, self.seed);
+        let mode = self.mode.name().as_bytes();
+        write_varint(&mut body, mode.len() as u64);
+        body.extend_from_slice(mode);
+        write_varint(&mut body, self.rules.len() as u64);
+        body.extend_from_slice(self.rules.as_bytes());
+        write_varint(&mut body, self.score as u64);
+        write_varint(&mut body, self.lines as u64);
+        write_varint(&mut body, self.frames);
+        write_varint(&mut body, self.board_hash);
+        write_varint(&mut body, self.actions.len() as u64);
+        let mut last_frame = 0;
+        for &(frame, action) in &self.actions {
+            write_varint(&mut body, frame - last_frame);
+            body.push(action_code(action));
+            last_frame = frame;
+        }
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&body).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(REPLAY_VERSION);
+        if compressed.len() < body.len() {
+            bytes.push(COMPRESSED);
+            bytes.extend(compressed);
+        } else {
+            bytes.push(0);
+            bytes.extend(body);
+        }
+        bytes
+    }
+
+    /// Decodes a replay encoded by `Replay::to_bytes`. Replays that could
+    /// be played back forever are refused as corrupt.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Replay, ReplayError> {
+        if bytes.len() < MAGIC.len() + 2 || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(ReplayError::Corrupt("header"));
+        }
+        let version = bytes[MAGIC.len()];
+        if version != REPLAY_VERSION {
+            return Err(ReplayError::Version(version));
+        }
+        let flags = bytes[MAGIC.len() + 1];
+        let rest = &bytes[MAGIC.len() + 2..];
+        let body = match flags & COMPRESSED {
+            0 => rest.to_vec(),
+            _ => {
+                let mut body = Vec::new();
+                DeflateDecoder::new(rest)
+                    .read_to_end(&mut body)
+                    .map_err(|_| ReplayError::Corrupt("compression"))?;
+                body
+            }
+        };
+
+        let mut reader = body.iter().copied();
+        let seed = field(&mut reader, "seed")?;
+        let mode_len = field(&mut reader, "mode")? as usize;
+        let mode = (&mut reader).take(mode_len).collect::<Vec<u8>>();
+        let mode = std::str::from_utf8(&mode)
+            .ok()
+            .and_then(Mode::from_name)
+            .ok_or(ReplayError::Corrupt("mode"))?;
+        // Endless games aren't recorded, and could be played back forever
+        if mode.endless() {
+            return Err(ReplayError::Corrupt("mode"));
+        }
+        let rules_len = field(&mut reader, "rules")? as usize;
+        let rules = (&mut reader).take(rules_len).collect::<Vec<u8>>();
+        let rules = String::from_utf8(rules).map_err(|_| ReplayError::Corrupt("rules"))?;
+        if rules != mode.rules() {
+            return Err(ReplayError::Rules(rules));
+        }
+        let score = field(&mut reader, "score")? as usize;
+        let lines = field(&mut reader, "lines")? as usize;
+        let frames = field(&mut reader, "frames")?;
+        let board_hash = field(&mut reader, "board")?;
+        let count = field(&mut reader, "actions")?;
+        let mut actions = Vec::new();
+        let mut frame: u64 = 0;
+        for _ in 0..count {
+            frame = frame
+                .checked_add(field(&mut reader, "actions")?)
+                .ok_or(ReplayError::Corrupt("actions"))?;
+            let action = reader
+                .next()
+                .and_then(parse_action)
+                .ok_or(ReplayError::Corrupt("actions"))?;
+            actions.push((frame, action));
+        }
+        // Playing back runs until `frames`, so it has to be in reach
+        if frames > frame.saturating_add(MAX_TAIL) {
+            return Err(ReplayError::Corrupt("frames"));
+        }
+        Ok(Replay {
+            seed,
+            mode,
+            rules,
+            actions,
+            score,
+            lines,
+            frames,
+            board_hash,
+        })
+    }
+}
+
+/// Plays a replay back through the game engine one frame at a time
+pub struct Playback {
+    /// The replay being played back
+    pub replay: Replay,
+    game: Game,
+    /// Index of the next action to apply
+    next_action: usize,
+    /// Copies of the game and `next_action`, every `KEYFRAME_INTERVAL` frames
+    keyframes: Vec<(Game, usize)>,
+}
+
+impl Playback {
+    /// Starts playing `replay` back from its first frame
+    pub fn new(replay: Replay) -> Self {
+        let game = Game::with_seed(replay.mode, replay.seed);
+        Playback {
+            replay,
+            keyframes: vec![(game.clone(), 0)],
+            game,
+            next_action: 0,
+        }
+    }
+
+    /// The game as of the frame played back to
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Whether the game has reached the end of the recording
+    pub fn finished(&self) -> bool {
+        self.game.game_over
+            || (self.next_action >= self.replay.actions.len()
+                && self.game.frame() >= self.replay.frames)
+    }
+
+    /// Applies the actions recorded for the current frame and advances the
+    /// game by one frame
+    pub fn step(&mut self) {
+        if self.finished() {
+            return;
+        }
+        while let Some(&(frame, action)) = self.replay.actions.get(self.next_action) {
+            if frame > self.game.frame() {
+                break;
+            }
+            self.game.apply(action);
+            self.next_action += 1;
+        }
+        self.game.update();
+        // Nothing listens to the game's events during playback
+        self.game.take_events();
+        let frame = self.game.frame();
+        let last_keyframe = self.keyframes.last().unwrap().0.frame();
+        if frame.is_multiple_of(KEYFRAME_INTERVAL) && frame > last_keyframe {
+            self.keyframes.push((self.game.clone(), self.next_action));
+        }
+    }
+
+    /// Moves to `frame`, starting from the closest earlier keyframe when
+    /// going backwards
+    pub fn seek(&mut self, frame: u64) {
+        if frame < self.game.frame() {
+            let (game, next_action) = self
+                .keyframes
+                .iter()
+                .rev()
+                .find(|(game, _)| game.frame() <= frame)
+                .unwrap();
+            self.game = game.clone();
+            self.next_action = *next_action;
+        }
+        while self.game.frame() < frame && !self.finished() {
+            let progress = (self.game.frame(), self.next_action);
+            self.step();
+            // Paused with no actions left to unpause
+            if progress == (self.game.frame(), self.next_action) {
+                break;
+            }
+        }
+    }
+
+    /// Plays the rest of the replay
+    pub fn run(&mut self) {
+        self.seek(u64::MAX);
+    }
+
+    /// Checks the played back result against the recorded one: the score
+    /// and lines, and how many frames it took to get a board with the same
+    /// hash
+    pub fn check(&self) -> Result<(), ReplayError> {
+        let results = [
+            ("score", self.replay.score as u64, self.game.score as u64),
+            ("lines", self.replay.lines as u64, self.game.lines as u64),
+            ("frames", self.replay.frames, self.game.frame()),
+            ("board", self.replay.board_hash, self.game.board_hash()),
+        ];
+        for (field, expected, actual) in results {
+            if expected != actual {
+                return Err(ReplayError::Divergence {
+                    field,
+                    expected,
+                    actual,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn field(reader: &mut impl Iterator<Item = u8>, name: &'static str) -> Result<u64, ReplayError> {
+    read_varint(reader).ok_or(ReplayError::Corrupt(name))
+}
+
+/// Writes `value` seven bits at a time, lowest first, with the high bit set
+/// on every byte but the last.
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &mut impl Iterator<Item = u8>) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = bytes.next()?;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn action_code(action: Action) -> u8 {
+    match action {
+        Action::Move(Move::Left) => 0,
+        Action::Move(Move::Right) => 1,
+        Action::Move(Move::Down) => 2,
+        Action::Move(Move::Rotate) => 3,
+        Action::Drop => 4,
+        Action::Hold => 5,
+        Action::Pause => 6,
+        Action::ToggleGravity => 7,
+        Action::ChooseNext(shape_type) => 8 + shape_type as u8,
+    }
+}
+
+fn parse_action(code: u8) -> Option<Action> {
+    match code {
+        0 => Some(Action::Move(Move::Left)),
+        1 => Some(Action::Move(Move::Right)),
+        2 => Some(Action::Move(Move::Down)),
+        3 => Some(Action::Move(Move::Rotate)),
+        4 => Some(Action::Drop),
+        5 => Some(Action::Hold),
+        6 => Some(Action::Pause),
+        7 => Some(Action::ToggleGravity),
+        _ => ShapeType::from_u8(code.checked_sub(8)?).map(Action::ChooseNext),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Replay {
+        Replay {
+            seed: 1234567890123,
+            mode: Mode::Master,
+            rules: Mode::Master.rules(),
+            actions: vec![
+                (0, Action::Move(Move::Left)),
+                (0, Action::Move(Move::Rotate)),
+                (130, Action::Drop),
+                (400, Action::Hold),
+                (100000, Action::ChooseNext(ShapeType::Z)),
+            ],
+            score: 4000,
+            lines: 2,
+            frames: 100500,
+            board_hash: 0xdead_beef,
+        }
+    }
+
+    /// Plays a game that hard drops every shape until it tops out
+    fn record(seed: u64) -> Replay {
+        let mut game = Game::with_seed(Mode::Marathon, seed);
+        let mut replay = Replay::new(&game);
+        let moves = [Move::Left, Move::Right, Move::Rotate, Move::Left];
+        while !game.game_over {
+            for _ in 0..60 {
+                game.update();
+            }
+            let action = Action::Move(moves[game.pieces % moves.len()]);
+            game.apply(action);
+            replay.record(game.frame(), action);
+            game.apply(Action::Drop);
+            replay.record(game.frame(), Action::Drop);
+        }
+        replay.finish(&game);
+        replay
+    }
+
+    #[test]
+    fn test_playback() {
+        let replay = record(5);
+        let mut playback = Playback::new(replay.clone());
+        playback.run();
+        assert!(playback.finished());
+        assert_eq!(playback.game().frame(), replay.frames);
+        assert!(playback.check().is_ok());
+    }
+
+    #[test]
+    fn test_playback_seek() {
+        let mut playback = Playback::new(record(9));
+        playback.seek(400);
+        let board = *playback.game().board_ref();
+        playback.run();
+        playback.seek(400);
+        assert_eq!(playback.game().frame(), 400);
+        assert_eq!(playback.game().board_ref(), &board);
+    }
+
+    #[test]
+    fn test_playback_divergence() {
+        let mut replay = record(5);
+        replay.score += 800;
+        let mut playback = Playback::new(replay.clone());
+        playback.run();
+        assert!(matches!(
+            playback.check(),
+            Err(ReplayError::Divergence { field: "score", .. })
+        ));
+        // Same score, but a different board
+        replay.score -= 800;
+        replay.board_hash ^= 1;
+        let mut playback = Playback::new(replay);
+        playback.run();
+        assert!(matches!(
+            playback.check(),
+            Err(ReplayError::Divergence { field: "board", .. })
+        ));
+    }
+
+    /// A replay body up to the actions, for the given mode
+    fn header(mode: Mode, frames: u64) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([REPLAY_VERSION, 0]);
+        write_varint(&mut bytes, 1);
+        write_varint(&mut bytes, mode.name().len() as u64);
+        bytes.extend_from_slice(mode.name().as_bytes());
+        write_varint(&mut bytes, mode.rules().len() as u64);
+        bytes.extend_from_slice(mode.rules().as_bytes());
+        for value in [0, 0, frames, 0] {
+            write_varint(&mut bytes, value);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_replay_malicious() {
+        // Action frames that overflow
+        let mut bytes = header(Mode::Marathon, 0);
+        write_varint(&mut bytes, 2);
+        for delta in [u64::MAX, 1] {
+            write_varint(&mut bytes, delta);
+            bytes.push(action_code(Action::Drop));
+        }
+        assert!(matches!(
+            Replay::from_bytes(&bytes),
+            Err(ReplayError::Corrupt("actions"))
+        ));
+        // Frames far past the last action
+        let mut bytes = header(Mode::Marathon, u64::MAX);
+        write_varint(&mut bytes, 0);
+        assert!(matches!(
+            Replay::from_bytes(&bytes),
+            Err(ReplayError::Corrupt("frames"))
+        ));
+        // Modes that never end
+        let mut bytes = header(Mode::Zen, 100);
+        write_varint(&mut bytes, 0);
+        assert!(matches!(
+            Replay::from_bytes(&bytes),
+            Err(ReplayError::Corrupt("mode"))
+        ));
+        let mut bytes = header(Mode::Marathon, 100);
+        write_varint(&mut bytes, 0);
+        assert!(Replay::from_bytes(&bytes).is_ok());
+    }
+
+    #[test]
+    fn test_varint() {
+        for value in [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, value);
+            assert_eq!(read_varint(&mut bytes.into_iter()), Some(value));
+        }
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, 127);
+        assert_eq!(bytes.len(), 1);
+    }
+
+    #[test]
+    fn test_replay_round_trip() {
+        let replay = sample();
+        assert_eq!(Replay::from_bytes(&replay.to_bytes()).unwrap(), replay);
+    }
+
+    #[test]
+    fn test_replay_compressed() {
+        let mut replay = sample();
+        for frame in 0..1000 {
+            replay.record(100500 + frame * 10, Action::Move(Move::Left));
+        }
+        let bytes = replay.to_bytes();
+        assert_eq!(bytes[MAGIC.len() + 1], COMPRESSED);
+        assert!(bytes.len() < replay.actions.len());
+        assert_eq!(Replay::from_bytes(&bytes).unwrap(), replay);
+    }
+
+    #[test]
+    fn test_replay_version_mismatch() {
+        let mut bytes = sample().to_bytes();
+        bytes[MAGIC.len()] = REPLAY_VERSION + 1;
+        assert!(matches!(
+            Replay::from_bytes(&bytes),
+            Err(ReplayError::Version(v)) if v == REPLAY_VERSION + 1
+        ));
+        assert!(matches!(
+            Replay::from_bytes(b"nope"),
+            Err(ReplayError::Corrupt("header"))
+        ));
+    }
+
+    #[test]
+    fn test_replay_rules_mismatch() {
+        let mut replay = sample();
+        replay.rules = Mode::Marathon.rules();
+        assert!(matches!(
+            Replay::from_bytes(&replay.to_bytes()),
+            Err(ReplayError::Rules(rules)) if rules.starts_with("rotation classic")
+        ));
+    }
+}
@@ -0,0 +1,168 @@
+//! Rotation systems: where shape cells go and which kicks are tried.
+
+use super::shapes::{Dir, Shape, ShapeTrait, ShapeType};
+
+/// Decides where the cells of a shape end up for a given direction and
+/// which offsets are tried when a rotation is blocked.
+///
+/// `Game` only talks to this trait, so every rotation system shares the
+/// same movement and collision code.
+pub trait RotationSystem: Sync {
+    /// Short name the system is recorded under in replays
+    fn name(&self) -> &'static str;
+
+    /// Returns the board coordinates of the shape's cells in the given
+    /// direction.
+    fn coords(&self, shape: &Shape, dir: Dir) -> Vec<(isize, isize)>;
+
+    /// Returns the (x, y) offsets to try, in order, when rotating `shape`
+    /// into `dir`. The first offset that fits is used.
+    ///
+    /// # Arguments
+    /// * `blocked` - Whether a board cell is already taken or off the board
+    fn kicks(
+        &self,
+        shape: &Shape,
+        dir: Dir,
+        blocked: &dyn Fn((isize, isize)) -> bool,
+    ) -> Vec<(isize, isize)>;
+}
+
+/// The game's own rotation system, using the `Shape::to_coords` states
+/// without wall kicks. Rotations that don't fit in place are rejected.
+pub struct Classic;
+
+/// Arika Rotation System, as used in the TGM series. The T, L and J shapes
+/// spawn flat side up, and blocked rotations are retried one cell to the
+/// right and then one cell to the left.
+pub struct Ars;
+
+/// The classic rotation system
+pub static CLASSIC: Classic = Classic;
+/// The ARS rotation system
+pub static ARS: Ars = Ars;
+
+impl RotationSystem for Classic {
+    fn name(&self) -> &'static str {
+        "classic"
+    }
+
+    fn coords(&self, shape: &Shape, dir: Dir) -> Vec<(isize, isize)> {
+        shape.to_coords(dir)
+    }
+
+    fn kicks(&self, _: &Shape, _: Dir, _: &dyn Fn((isize, isize)) -> bool) -> Vec<(isize, isize)> {
+        vec![(0, 0)]
+    }
+}
+
+impl Ars {
+    /// Cell offsets from the shape's position, which sits at the centre of
+    /// the 3x3 rotation box (second row, second column for the I shape).
+    /// Offsets are listed top to bottom, left to right.
+    fn offsets(shape_type: ShapeType, dir: Dir) -> [(isize, isize); 4] {
+        match (shape_type, dir) {
+            (ShapeType::I, Dir::Right | Dir::Left) => [(-1, 0), (0, 0), (1, 0), (2, 0)],
+            (ShapeType::I, Dir::Down | Dir::Up) => [(1, -1), (1, 0), (1, 1), (1, 2)],
+            (ShapeType::J, Dir::Right) => [(-1, 0), (0, 0), (1, 0), (1, 1)],
+            (ShapeType::J, Dir::Down) => [(0, -1), (0, 0), (-1, 1), (0, 1)],
+            (ShapeType::J, Dir::Left) => [(-1, 0), (-1, 1), (0, 1), (1, 1)],
+            (ShapeType::J, Dir::Up) => [(0, -1), (1, -1), (0, 0), (0, 1)],
+            (ShapeType::L, Dir::Right) => [(-1, 0), (0, 0), (1, 0), (-1, 1)],
+            (ShapeType::L, Dir::Down) => [(-1, -1), (0, -1), (0, 0), (0, 1)],
+            (ShapeType::L, Dir::Left) => [(1, 0), (-1, 1), (0, 1), (1, 1)],
+            (ShapeType::L, Dir::Up) => [(0, -1), (0, 0), (0, 1), (1, 1)],
+            (ShapeType::O, _) => [(0, 0), (1, 0), (0, 1), (1, 1)],
+            (ShapeType::S, Dir::Right | Dir::Left) => [(0, 0), (1, 0), (-1, 1), (0, 1)],
+            (ShapeType::S, Dir::Down | Dir::Up) => [(-1, -1), (-1, 0), (0, 0), (0, 1)],
+            (ShapeType::T, Dir::Right) => [(-1, 0), (0, 0), (1, 0), (0, 1)],
+            (ShapeType::T, Dir::Down) => [(0, -1), (-1, 0), (0, 0), (0, 1)],
+            (ShapeType::T, Dir::Left) => [(0, 0), (-1, 1), (0, 1), (1, 1)],
+            (ShapeType::T, Dir::Up) => [(0, -1), (0, 0), (1, 0), (0, 1)],
+            (ShapeType::Z, Dir::Right | Dir::Left) => [(-1, 0), (0, 0), (0, 1), (1, 1)],
+            (ShapeType::Z, Dir::Down | Dir::Up) => [(1, -1), (0, 0), (1, 0), (0, 1)],
+        }
+    }
+}
+
+impl RotationSystem for Ars {
+    fn name(&self) -> &'static str {
+        "ars"
+    }
+
+    fn coords(&self, shape: &Shape, dir: Dir) -> Vec<(isize, isize)> {
+        Ars::offsets(shape.shape_type, dir)
+            .iter()
+            .map(|(dx, dy)| (shape.x + dx, shape.y + dy))
+            .collect()
+    }
+
+    fn kicks(
+        &self,
+        shape: &Shape,
+        dir: Dir,
+        blocked: &dyn Fn((isize, isize)) -> bool,
+    ) -> Vec<(isize, isize)> {
+        match shape.shape_type {
+            // The I shape never kicks
+            ShapeType::I => vec![(0, 0)],
+            // L, J and T don't kick when the first blocked cell is in the
+            // centre column
+            ShapeType::J | ShapeType::L | ShapeType::T => {
+                let first_blocked = self
+                    .coords(shape, dir)
+                    .into_iter()
+                    .find(|&cell| blocked(cell));
+                match first_blocked {
+                    Some((x, _)) if x == shape.x => vec![(0, 0)],
+                    _ => vec![(0, 0), (1, 0), (-1, 0)],
+                }
+            }
+            _ => vec![(0, 0), (1, 0), (-1, 0)],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ars_coords_t() {
+        let shape = Shape::new(4, 4, ShapeType::T);
+        assert_eq!(
+            ARS.coords(&shape, Dir::Right),
+            vec![(3, 4), (4, 4), (5, 4), (4, 5)]
+        );
+        assert_eq!(
+            ARS.coords(&shape, Dir::Left),
+            vec![(4, 4), (3, 5), (4, 5), (5, 5)]
+        );
+    }
+
+    #[test]
+    fn test_ars_kicks() {
+        let shape = Shape::new(4, 4, ShapeType::S);
+        assert_eq!(
+            ARS.kicks(&shape, Dir::Down, &|_| false),
+            vec![(0, 0), (1, 0), (-1, 0)]
+        );
+        let shape = Shape::new(4, 4, ShapeType::I);
+        assert_eq!(ARS.kicks(&shape, Dir::Down, &|_| false), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn test_ars_centre_column_rule() {
+        let shape = Shape::new(4, 4, ShapeType::T);
+        // Blocked above the centre: no kicks
+        assert_eq!(
+            ARS.kicks(&shape, Dir::Down, &|cell| cell == (4, 3)),
+            vec![(0, 0)]
+        );
+        // Blocked to the left: kicks allowed
+        assert_eq!(
+            ARS.kicks(&shape, Dir::Down, &|cell| cell == (3, 4)),
+            vec![(0, 0), (1, 0), (-1, 0)]
+        );
+    }
+}
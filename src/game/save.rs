@@ -0,0 +1,448 @@
+//! Saving a game in progress and resuming it.
+
+use super::mode::Mode;
+use super::randomizer::Randomizer;
+use super::shapes::{Dir, Shape, ShapeType};
+use super::stats::Stats;
+use super::{fill_preview, Game, HEIGHT, PSIZE, WIDTH};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+/// Bumped whenever the save format changes. Saves from other versions are
+/// refused rather than guessed at.
+pub const SAVE_VERSION: u32 = 4;
+const HEADER: &str = "tetris-save";
+
+/// Why a saved game couldn't be loaded
+#[derive(Debug)]
+pub enum SaveError {
+    /// The save file couldn't be read or written
+    Io(std::io::Error),
+    /// The save was written by a different format version
+    Version(String),
+    /// The save couldn't be read, with the field that was bad
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "could not read saved game: {}", e),
+            SaveError::Version(v) => write!(
+                f,
+                "saved game has format version {}, expected {}",
+                v, SAVE_VERSION
+            ),
+            SaveError::Corrupt(field) => write!(f, "saved game is corrupt ({})", field),
+        }
+    }
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(e: std::io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+impl Game {
+    /// Writes the game to `path` so it can be resumed with `Game::load`
+    pub fn save(&self, path: &Path) -> Result<(), SaveError> {
+        std::fs::write(path, self.to_save())?;
+        Ok(())
+    }
+
+    /// Reads a game written by `Game::save`
+    pub fn load(path: &Path) -> Result<Game, SaveError> {
+        Game::from_save(&std::fs::read_to_string(path)?)
+    }
+
+    /// Returns the game as a line based `key value` text
+    pub fn to_save(&self) -> String {
+        let mut lines = vec![
+            format!("{} {}", HEADER, SAVE_VERSION),
+            format!("mode {}", self.mode.name()),
+            format!("seed {}", self.seed),
+            format!("rng {}", self.rng.state()),
+            format!("next {}", self.next_shape as u8),
+            format!("hold {}", self.hold_shape.map_or(0, |s| s as u8)),
+            format!("can_hold {}", self.can_hold as u8),
+            match self.active_shape {
+                Some(shape) => format!(
+                    "active {} {} {} {}",
+                    shape.shape_type as u8,
+                    shape.x,
+                    shape.y,
+                    dir_name(shape.dir)
+                ),
+                None => "active -".to_string(),
+            },
+            format!("score {}", self.score),
+            format!("lines {}", self.lines),
+            format!("pieces {}", self.pieces),
+            format!("gravity {}", self.gravity as u8),
+            format!("frame {}", self.frame),
+            format!("fall_timer {}", self.fall_timer),
+            format!("lock_timer {}", self.lock_timer),
+            format!("entry_timer {}", timer_name(self.entry_timer)),
+            format!("reveal_timer {}", timer_name(self.reveal_timer)),
+            format!(
+                "clearing {}",
+                self.clearing
+                    .iter()
+                    .map(|y| y.to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+            format!(
+                "initial {} {}",
+                self.initial_rotate as u8, self.initial_hold as u8
+            ),
+            format!("stats {}", stats_text(&self.stats)),
+        ];
+        for row in &self.board {
+            let cells = row.iter().map(|c| c.to_string()).collect::<String>();
+            lines.push(format!("board {}", cells));
+        }
+        for row in &self.locked_at {
+            let frames = row
+                .iter()
+                .map(|&t| timer_name(t))
+                .collect::<Vec<String>>()
+                .join(",");
+            lines.push(format!("locked {}", frames));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    /// Reads a game from the text `Game::to_save` writes. Values that
+    /// couldn't come from a real game are refused as corrupt.
+    pub fn from_save(text: &str) -> Result<Game, SaveError> {
+        let mut lines = text.lines();
+        match lines.next().and_then(|l| l.strip_prefix(HEADER)) {
+            Some(version) if version.trim() == SAVE_VERSION.to_string() => {}
+            Some(version) => return Err(SaveError::Version(version.trim().to_string())),
+            None => return Err(SaveError::Corrupt("header")),
+        }
+        let mut fields: HashMap<&str, Vec<&str>> = HashMap::new();
+        for line in lines {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            fields.entry(key).or_default().push(value);
+        }
+        let field = |key: &'static str| -> Result<&str, SaveError> {
+            fields
+                .get(key)
+                .and_then(|v| v.first().copied())
+                .ok_or(SaveError::Corrupt(key))
+        };
+        let number = |key: &'static str| -> Result<u64, SaveError> {
+            field(key)?.parse().map_err(|_| SaveError::Corrupt(key))
+        };
+        let shape_type = |key: &'static str, value: &str| -> Result<ShapeType, SaveError> {
+            value
+                .parse()
+                .ok()
+                .and_then(ShapeType::from_u8)
+                .ok_or(SaveError::Corrupt(key))
+        };
+
+        let mode = Mode::from_name(field("mode")?).ok_or(SaveError::Corrupt("mode"))?;
+        let mut game = Game::with_seed(mode, number("seed")?);
+        game.rng = Randomizer::new(number("rng")?);
+        game.next_shape = shape_type("next", field("next")?)?;
+        game.hold_shape = match field("hold")? {
+            "0" => None,
+            value => Some(shape_type("hold", value)?),
+        };
+        if let Some(hold_shape) = game.hold_shape {
+            fill_preview(&mut game.hold_board, hold_shape);
+        }
+        game.can_hold = number("can_hold")? != 0;
+        game.active_shape = match field("active")? {
+            "-" => None,
+            value => Some(parse_shape(value).ok_or(SaveError::Corrupt("active"))?),
+        };
+        game.score = number("score")? as usize;
+        game.lines = number("lines")? as usize;
+        game.pieces = number("pieces")? as usize;
+        game.gravity = number("gravity")? != 0;
+        game.frame = number("frame")?;
+        // Timers count frames since they were started, so none can start
+        // after the current frame
+        let frame = game.frame;
+        let started = |key: &'static str, start: u64| {
+            if start <= frame {
+                Ok(start)
+            } else {
+                Err(SaveError::Corrupt(key))
+            }
+        };
+        let timer = |key: &'static str| -> Result<Option<u64>, SaveError> {
+            parse_timer(field(key)?)
+                .ok_or(SaveError::Corrupt(key))?
+                .map(|start| started(key, start))
+                .transpose()
+        };
+        game.fall_timer = started("fall_timer", number("fall_timer")?)?;
+        game.lock_timer = started("lock_timer", number("lock_timer")?)?;
+        game.entry_timer = timer("entry_timer")?;
+        game.reveal_timer = timer("reveal_timer")?;
+        game.clearing = field("clearing")?
+            .split(',')
+            .filter(|y| !y.is_empty())
+            .map(|y| y.parse().ok().filter(|&y| y < HEIGHT))
+            .collect::<Option<Vec<usize>>>()
+            .ok_or(SaveError::Corrupt("clearing"))?;
+        let initial = field("initial")?;
+        game.initial_rotate = initial.starts_with('1');
+        game.initial_hold = initial.ends_with('1');
+        game.stats = parse_stats(field("stats")?).ok_or(SaveError::Corrupt("stats"))?;
+
+        let rows = fields.get("board").ok_or(SaveError::Corrupt("board"))?;
+        if rows.len() != HEIGHT {
+            return Err(SaveError::Corrupt("board"));
+        }
+        for (y, row) in rows.iter().enumerate() {
+            let cells = row
+                .chars()
+                .map(|c| c.to_digit(10).filter(|&d| d <= 7).map(|d| d as u8))
+                .collect::<Option<Vec<u8>>>()
+                .filter(|cells| cells.len() == WIDTH)
+                .ok_or(SaveError::Corrupt("board"))?;
+            game.board[y].copy_from_slice(&cells);
+        }
+        let rows = fields.get("locked").ok_or(SaveError::Corrupt("locked"))?;
+        if rows.len() != HEIGHT {
+            return Err(SaveError::Corrupt("locked"));
+        }
+        for (y, row) in rows.iter().enumerate() {
+            let cells = row
+                .split(',')
+                .map(parse_timer)
+                .collect::<Option<Vec<Option<u64>>>>()
+                .filter(|cells| cells.len() == WIDTH)
+                .ok_or(SaveError::Corrupt("locked"))?
+                .into_iter()
+                .map(|cell| cell.map(|start| started("locked", start)).transpose())
+                .collect::<Result<Vec<Option<u64>>, SaveError>>()?;
+            game.locked_at[y].copy_from_slice(&cells);
+        }
+        // The active shape is drawn on the board, so its cells can only
+        // hold it or be empty
+        if let Some(shape) = game.active_shape {
+            let fits = game
+                .rotation
+                .coords(&shape, shape.dir)
+                .into_iter()
+                .all(|(x, y)| {
+                    (0..WIDTH as isize).contains(&x)
+                        && y < HEIGHT as isize
+                        && (y < 0
+                            || [0, shape.shape_type as u8]
+                                .contains(&game.board[y as usize][x as usize]))
+                });
+            if !fits {
+                return Err(SaveError::Corrupt("active"));
+            }
+        }
+        game.update_preview_board();
+        Ok(game)
+    }
+}
+
+fn dir_name(dir: Dir) -> &'static str {
+    match dir {
+        Dir::Right => "right",
+        Dir::Down => "down",
+        Dir::Left => "left",
+        Dir::Up => "up",
+    }
+}
+
+fn parse_shape(value: &str) -> Option<Shape> {
+    let parts = value.split(' ').collect::<Vec<&str>>();
+    let [shape_type, x, y, dir] = parts[..] else {
+        return None;
+    };
+    // The pivot is within a shape's width of the board
+    let near = |value: &str, size: usize| {
+        value
+            .parse()
+            .ok()
+            .filter(|&value: &isize| value > -(PSIZE as isize) && value < (size + PSIZE) as isize)
+    };
+    let mut shape = Shape::new(
+        near(x, WIDTH)?,
+        near(y, HEIGHT)?,
+        ShapeType::from_u8(shape_type.parse().ok()?)?,
+    );
+    shape.dir = match dir {
+        "right" => Dir::Right,
+        "down" => Dir::Down,
+        "left" => Dir::Left,
+        "up" => Dir::Up,
+        _ => return None,
+    };
+    Some(shape)
+}
+
+/// Keys, T-spins, combo, max combo, back-to-back and attack, then the four
+/// clear counts and the seven piece counts
+fn stats_text(stats: &Stats) -> String {
+    [
+        stats.keys,
+        stats.t_spins,
+        stats.combo,
+        stats.max_combo,
+        stats.back_to_back,
+        stats.attack,
+    ]
+    .iter()
+    .chain(&stats.clears)
+    .chain(&stats.distribution)
+    .map(|n| n.to_string())
+    .collect::<Vec<String>>()
+    .join(" ")
+}
+
+fn parse_stats(value: &str) -> Option<Stats> {
+    let numbers = value
+        .split(' ')
+        .map(|n| n.parse().ok())
+        .collect::<Option<Vec<usize>>>()?;
+    let [keys, t_spins, combo, max_combo, back_to_back, attack, ref counts @ ..] = numbers[..]
+    else {
+        return None;
+    };
+    let (clears, distribution) = (counts.get(..4)?, counts.get(4..)?);
+    Some(Stats {
+        keys,
+        t_spins,
+        combo,
+        max_combo,
+        back_to_back,
+        attack,
+        clears: clears.try_into().ok()?,
+        distribution: distribution.try_into().ok()?,
+    })
+}
+
+/// The frame the timer was started on, or `-` if it isn't running
+fn timer_name(timer: Option<u64>) -> String {
+    match timer {
+        Some(frame) => frame.to_string(),
+        None => "-".to_string(),
+    }
+}
+
+fn parse_timer(value: &str) -> Option<Option<u64>> {
+    match value {
+        "-" => Some(None),
+        frame => Some(Some(frame.parse().ok()?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_round_trip() {
+        let mut game = Game::with_seed(Mode::Fading, 11);
+        game.update();
+        game.drop_shape();
+        game.update();
+        game.hold();
+        game.update();
+        game.move_shape(super::super::Move::Rotate);
+
+        let loaded = Game::from_save(&game.to_save()).unwrap();
+        assert_eq!(loaded.mode, Mode::Fading);
+        assert_eq!(loaded.board, game.board);
+        assert_eq!(loaded.active_shape, game.active_shape);
+        assert_eq!(loaded.next_shape, game.next_shape);
+        assert_eq!(loaded.hold_shape, game.hold_shape);
+        assert_eq!(loaded.can_hold, game.can_hold);
+        assert_eq!(loaded.seed, game.seed);
+        assert_eq!(loaded.rng, game.rng);
+        assert_eq!(loaded.score, game.score);
+        assert_eq!(loaded.pieces, game.pieces);
+        assert_eq!(loaded.frame, game.frame);
+        assert_eq!(loaded.locked_at, game.locked_at);
+        assert_eq!(loaded.stats, game.stats);
+    }
+
+    #[test]
+    fn test_save_version_mismatch() {
+        let text = Game::new()
+            .to_save()
+            .replacen("tetris-save 4", "tetris-save 99", 1);
+        assert!(matches!(Game::from_save(&text), Err(SaveError::Version(v)) if v == "99"));
+    }
+
+    #[test]
+    fn test_save_corrupt() {
+        assert!(matches!(Game::from_save(""), Err(SaveError::Corrupt(_))));
+        let text = Game::new().to_save().replace("board 000000000000\n", "");
+        assert!(matches!(
+            Game::from_save(&text),
+            Err(SaveError::Corrupt("board"))
+        ));
+    }
+
+    #[test]
+    fn test_save_active_out_of_place() {
+        let mut game = Game::new();
+        game.update();
+        let shape = game.active_shape.unwrap();
+        let line = |x: &str, y: &str| {
+            let (shape_type, dir) = (shape.shape_type as u8, dir_name(shape.dir));
+            format!("active {} {} {} {}", shape_type, x, y, dir)
+        };
+        let active = line(&shape.x.to_string(), &shape.y.to_string());
+        let moved = |x: &str, y: &str| game.to_save().replacen(&active, &line(x, y), 1);
+        assert!(Game::from_save(&moved("5", "10")).is_ok());
+        for (x, y) in [
+            ("-9223372036854775808", "0"),
+            ("0", "9223372036854775807"),
+            ("-1", "5"),
+            ("5", "20"),
+        ] {
+            assert!(matches!(
+                Game::from_save(&moved(x, y)),
+                Err(SaveError::Corrupt("active"))
+            ));
+        }
+        // On top of cells of another shape
+        let other = (shape.shape_type as u8 % 7 + 1).to_string();
+        let text = moved("5", "10").replace(
+            &format!("board {}", "0".repeat(WIDTH)),
+            &format!("board {}", other.repeat(WIDTH)),
+        );
+        assert!(matches!(
+            Game::from_save(&text),
+            Err(SaveError::Corrupt("active"))
+        ));
+    }
+
+    #[test]
+    fn test_save_timer_after_frame() {
+        let mut game = Game::new();
+        game.update();
+        game.drop_shape();
+        let text = game.to_save();
+        let frame = format!("frame {}\n", game.frame);
+        let text = text.replacen(&frame, "frame 0\n", 1);
+        assert!(matches!(
+            Game::from_save(&text),
+            Err(SaveError::Corrupt("fall_timer" | "lock_timer" | "locked"))
+        ));
+        let text = game
+            .to_save()
+            .replacen("entry_timer -", "entry_timer 999", 1);
+        assert!(matches!(
+            Game::from_save(&text),
+            Err(SaveError::Corrupt("entry_timer"))
+        ));
+    }
+}
@@ -1,48 +1,69 @@
-use rand::Rng;
+//! The seven shapes and their rotation states.
 
+/// The direction a shape is facing. Rotating steps through them in order.
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum Dir {
+    /// The way shapes spawn facing
     Right,
+    /// One turn on from `Right`
     Down,
+    /// Two turns on from `Right`
     Left,
+    /// Three turns on from `Right`
     Up,
 }
 
+/// The seven tetrominoes. The value is what the shape leaves on the board.
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum ShapeType {
+    /// Four in a line
     I = 1,
+    /// Three in a line with one beside an end, the mirror image of `L`
     J = 2,
+    /// Three in a line with one beside an end, the mirror image of `J`
     L = 3,
+    /// Two by two
     O = 4,
+    /// Two offset pairs, the mirror image of `Z`
     S = 5,
+    /// Three in a line with one beside the middle
     T = 6,
+    /// Two offset pairs, the mirror image of `S`
     Z = 7,
 }
 
 impl ShapeType {
-    pub(crate) fn random() -> ShapeType {
-        match rand::thread_rng().gen_range(0..=6) {
-            0 => ShapeType::I,
-            1 => ShapeType::J,
-            2 => ShapeType::L,
-            3 => ShapeType::O,
-            4 => ShapeType::S,
-            5 => ShapeType::T,
-            _ => ShapeType::Z,
+    /// Returns the shape type for a board cell value
+    pub fn from_u8(value: u8) -> Option<ShapeType> {
+        match value {
+            1 => Some(ShapeType::I),
+            2 => Some(ShapeType::J),
+            3 => Some(ShapeType::L),
+            4 => Some(ShapeType::O),
+            5 => Some(ShapeType::S),
+            6 => Some(ShapeType::T),
+            7 => Some(ShapeType::Z),
+            _ => None,
         }
     }
 }
 
+/// A tetromino at a board position
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct Shape {
+    /// The way the shape is facing
     pub dir: Dir,
+    /// Which of the seven shapes it is
     pub shape_type: ShapeType,
-    pub x: usize,
+    /// Board column of the shape's pivot cell
+    pub x: isize,
+    /// Board row of the shape's pivot cell, from the top
     pub y: isize,
 }
 
 impl Shape {
-    pub fn new(x: usize, y: isize, shape_type: ShapeType) -> Self {
+    /// Creates a shape facing right at (x, y)
+    pub fn new(x: isize, y: isize, shape_type: ShapeType) -> Self {
         Shape {
             dir: Dir::Right,
             shape_type,
@@ -52,7 +73,7 @@ impl Shape {
     }
 
     fn to_coords_i(&self, dir: Dir) -> Vec<(isize, isize)> {
-        let x = self.x as isize;
+        let x = self.x;
         let y = self.y as isize;
         match dir {
             Dir::Right | Dir::Left => vec![(x, y), (x - 1, y), (x + 1, y), (x + 2, y)],
@@ -61,7 +82,7 @@ impl Shape {
     }
 
     fn to_coords_j(&self, dir: Dir) -> Vec<(isize, isize)> {
-        let x = self.x as isize;
+        let x = self.x;
         let y = self.y as isize;
         match dir {
             Dir::Right => vec![(x, y), (x - 1, y - 1), (x - 1, y), (x + 1, y)],
@@ -72,7 +93,7 @@ impl Shape {
     }
 
     fn to_coords_l(&self, dir: Dir) -> Vec<(isize, isize)> {
-        let x = self.x as isize;
+        let x = self.x;
         let y = self.y as isize;
         match dir {
             Dir::Right => vec![(x, y), (x - 1, y), (x + 1, y), (x - 1, y + 1)],
@@ -83,13 +104,13 @@ impl Shape {
     }
 
     fn to_coords_o(&self, _: Dir) -> Vec<(isize, isize)> {
-        let x = self.x as isize;
+        let x = self.x;
         let y = self.y as isize;
         vec![(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)]
     }
 
     fn to_coords_s(&self, dir: Dir) -> Vec<(isize, isize)> {
-        let x = self.x as isize;
+        let x = self.x;
         let y = self.y as isize;
         match dir {
             Dir::Right | Dir::Left => vec![(x, y), (x, y - 1), (x + 1, y - 1), (x - 1, y)],
@@ -98,7 +119,7 @@ impl Shape {
     }
 
     fn to_coords_t(&self, dir: Dir) -> Vec<(isize, isize)> {
-        let x = self.x as isize;
+        let x = self.x;
         let y = self.y as isize;
         match dir {
             Dir::Right => vec![(x, y), (x, y - 1), (x + 1, y), (x, y + 1)],
@@ -109,7 +130,7 @@ impl Shape {
     }
 
     fn to_coords_z(&self, dir: Dir) -> Vec<(isize, isize)> {
-        let x = self.x as isize;
+        let x = self.x;
         let y = self.y as isize;
         match dir {
             Dir::Right | Dir::Left => vec![(x, y), (x - 1, y), (x, y + 1), (x + 1, y + 1)],
@@ -118,8 +139,11 @@ impl Shape {
     }
 }
 
+/// Where a shape's cells are and how it turns
 pub trait ShapeTrait {
+    /// Board coordinates of the shape's cells when facing `dir`
     fn to_coords(&self, dir: Dir) -> Vec<(isize, isize)>;
+    /// The direction a turn from `dir` faces
     fn next_dir(&self, dir: Dir) -> Dir;
 }
 
@@ -1,44 +1,442 @@
-use soloud::*;
-use std::path::Path;
-use tokio::sync::mpsc::Receiver;
+//! Sound effects, music and volume, behind an audio backend.
 
-pub struct Player {
-    pub sl: Soloud,
-    pub wav: audio::Wav,
-    playing: bool,
-    sound_file: String,
-    rx: Receiver<usize>,
+use super::Event;
+use std::path::{Path, PathBuf};
+
+/// File extensions tried for each sound, in order
+const EXTENSIONS: [&str; 4] = ["wav", "ogg", "mp3", "flac"];
+/// Steps between silent and full volume
+pub const VOLUME_STEPS: u8 = 10;
+/// Stack height at which the music speeds up
+pub const DANGER_HEIGHT: usize = 14;
+/// Music speed while the stack is at or above `DANGER_HEIGHT`
+const DANGER_TEMPO: f32 = 1.25;
+/// Music speed added for every level after the first
+const LEVEL_TEMPO: f32 = 0.02;
+const MAX_LEVEL_TEMPO: f32 = 1.2;
+
+/// A short sound effect played over the music
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Sfx {
+    /// The shape moves left or right
+    Move,
+    /// The shape rotates
+    Rotate,
+    /// The shape lands
+    Lock,
+    /// One line is cleared
+    Single,
+    /// Two lines are cleared
+    Double,
+    /// Three lines are cleared
+    Triple,
+    /// Four lines are cleared
+    Tetris,
+    /// A T shape is spun into place
+    TSpin,
+    /// A shape is held
+    Hold,
+    /// The level goes up
+    LevelUp,
+    /// The game ends
+    GameOver,
 }
 
-impl Player {
-    pub fn new(sound_file: &str, rx: Receiver<usize>) -> Self {
-        Self {
-            sl: Soloud::default().unwrap(),
-            wav: audio::Wav::default(),
-            playing: false,
-            sound_file: sound_file.to_string(),
-            rx,
+impl Sfx {
+    /// Every effect, in the order of the sound pack table
+    pub const ALL: [Sfx; 11] = [
+        Sfx::Move,
+        Sfx::Rotate,
+        Sfx::Lock,
+        Sfx::Single,
+        Sfx::Double,
+        Sfx::Triple,
+        Sfx::Tetris,
+        Sfx::TSpin,
+        Sfx::Hold,
+        Sfx::LevelUp,
+        Sfx::GameOver,
+    ];
+
+    /// Returns the effect played for a game event
+    pub fn for_event(event: Event) -> Sfx {
+        match event {
+            Event::Move => Sfx::Move,
+            Event::Rotate => Sfx::Rotate,
+            Event::Lock => Sfx::Lock,
+            Event::LineClear(1) => Sfx::Single,
+            Event::LineClear(2) => Sfx::Double,
+            Event::LineClear(3) => Sfx::Triple,
+            Event::LineClear(_) => Sfx::Tetris,
+            Event::TSpin => Sfx::TSpin,
+            Event::Hold => Sfx::Hold,
+            Event::LevelUp => Sfx::LevelUp,
+            Event::GameOver => Sfx::GameOver,
         }
     }
 
-    pub fn play(&mut self) {
-        if self.playing {
-            return;
+    /// File names, without extension, looked for in a sound pack. Later
+    /// names are fallbacks for packs that don't have the first one.
+    fn file_names(self) -> &'static [&'static str] {
+        match self {
+            Sfx::Move => &["move"],
+            Sfx::Rotate => &["rotate", "move"],
+            Sfx::Lock => &["lock"],
+            Sfx::Single => &["single"],
+            Sfx::Double => &["double", "single"],
+            Sfx::Triple => &["triple", "double", "single"],
+            Sfx::Tetris => &["tetris", "triple", "double", "single"],
+            Sfx::TSpin => &["tspin", "rotate"],
+            Sfx::Hold => &["hold", "move"],
+            Sfx::LevelUp => &["levelup"],
+            Sfx::GameOver => &["gameover"],
+        }
+    }
+}
+
+/// Commands from a front end to whatever is playing its audio
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum AudioCommand {
+    /// Plays an effect
+    Effect(Sfx),
+    /// Mutes or unmutes everything
+    ToggleMute,
+    /// Turns the music up a step
+    MusicVolumeUp,
+    /// Turns the music down a step
+    MusicVolumeDown,
+    /// Turns the effects up a step
+    SfxVolumeUp,
+    /// Turns the effects down a step
+    SfxVolumeDown,
+    /// Skips to the next track
+    NextTrack,
+    /// Goes back to the previous track
+    PreviousTrack,
+    /// Sets the music speed, 1 being normal
+    Tempo(f32),
+    /// Stops all sound for the rest of the session
+    Stop,
+}
+
+/// Music and effect volumes, in steps from 0 to `VOLUME_STEPS`
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Volume {
+    /// Music volume
+    pub music: u8,
+    /// Sound effect volume
+    pub sfx: u8,
+    /// Silences everything, keeping the volumes for unmuting
+    pub muted: bool,
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Volume {
+            music: VOLUME_STEPS,
+            sfx: VOLUME_STEPS,
+            muted: false,
+        }
+    }
+}
+
+impl Volume {
+    /// Applies a volume command. Returns false for commands that don't
+    /// change the volume.
+    pub fn apply(&mut self, command: AudioCommand) -> bool {
+        match command {
+            AudioCommand::ToggleMute => self.muted = !self.muted,
+            AudioCommand::MusicVolumeUp => self.music = (self.music + 1).min(VOLUME_STEPS),
+            AudioCommand::MusicVolumeDown => self.music = self.music.saturating_sub(1),
+            AudioCommand::SfxVolumeUp => self.sfx = (self.sfx + 1).min(VOLUME_STEPS),
+            AudioCommand::SfxVolumeDown => self.sfx = self.sfx.saturating_sub(1),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Music volume from 0 to 1, taking mute into account
+    pub fn music_gain(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.music as f32 / VOLUME_STEPS as f32
+        }
+    }
+
+    /// Effect volume from 0 to 1, taking mute into account
+    pub fn sfx_gain(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.sfx as f32 / VOLUME_STEPS as f32
+        }
+    }
+}
+
+impl std::fmt::Display for Volume {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.muted {
+            write!(f, "Sound muted")
+        } else {
+            write!(
+                f,
+                "Music: {}/{} | Effects: {}/{}",
+                self.music, VOLUME_STEPS, self.sfx, VOLUME_STEPS
+            )
+        }
+    }
+}
+
+/// Returns the music speed for a game at `level` with a stack
+/// `stack_height` rows high. The music gets a little faster every level and
+/// a lot faster while the stack is in danger of topping out.
+pub fn tempo(level: usize, stack_height: usize) -> f32 {
+    let tempo = (1.0 + level.saturating_sub(1) as f32 * LEVEL_TEMPO).min(MAX_LEVEL_TEMPO);
+    if stack_height >= DANGER_HEIGHT {
+        tempo * DANGER_TEMPO
+    } else {
+        tempo
+    }
+}
+
+/// Finds the file for `sfx` in the sound pack at `dir`, if there is one
+pub fn find_effect(dir: &Path, sfx: Sfx) -> Option<PathBuf> {
+    sfx.file_names().iter().find_map(|name| {
+        EXTENSIONS
+            .iter()
+            .map(|ext| dir.join(name).with_extension(ext))
+            .find(|path| path.is_file())
+    })
+}
+
+/// Plays the game's audio. Front ends talk to this trait so the engine
+/// builds and runs the same with or without an audio library.
+pub trait AudioBackend {
+    /// Starts playing the music in `path` in place of any music already
+    /// playing. Files that can't be loaded are skipped silently. A looping
+    /// track starts over without a gap.
+    fn play_music(&mut self, path: &Path, looping: bool);
+    /// Whether the music started last has played to the end, or couldn't be
+    /// loaded
+    fn music_finished(&self) -> bool;
+    /// Loads the sound effects. Effects are taken from the sound pack at
+    /// `pack` where it has a file for them and synthesized otherwise.
+    fn load_effects(&mut self, pack: Option<&Path>);
+    /// Plays a loaded effect over the music
+    fn play_effect(&mut self, sfx: Sfx);
+    /// Sets the music speed, 1 being normal
+    fn set_tempo(&mut self, tempo: f32);
+    /// Sets the music and effect volumes, each from 0 to 1
+    fn set_volume(&mut self, music: f32, sfx: f32);
+    /// Stops everything that is playing
+    fn stop(&mut self);
+}
+
+/// Backend that plays nothing. Used when the `sound` feature is off or no
+/// audio device could be opened.
+pub struct NullAudio;
+
+impl AudioBackend for NullAudio {
+    fn play_music(&mut self, _path: &Path, _looping: bool) {}
+
+    fn music_finished(&self) -> bool {
+        false
+    }
+
+    fn load_effects(&mut self, _pack: Option<&Path>) {}
+
+    fn play_effect(&mut self, _sfx: Sfx) {}
+
+    fn set_tempo(&mut self, _tempo: f32) {}
+
+    fn set_volume(&mut self, _music: f32, _sfx: f32) {}
+
+    fn stop(&mut self) {}
+}
+
+/// Returns the best backend available in this build
+pub fn backend() -> Box<dyn AudioBackend> {
+    #[cfg(feature = "sound")]
+    if let Some(audio) = soloud_backend::SoloudAudio::new() {
+        return Box::new(audio);
+    }
+    Box::new(NullAudio)
+}
+
+#[cfg(feature = "sound")]
+mod soloud_backend {
+    use super::super::synth;
+    use super::{find_effect, AudioBackend, Sfx};
+    use soloud::*;
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    pub struct SoloudAudio {
+        sl: Soloud,
+        music: audio::Wav,
+        music_voice: Option<Handle>,
+        /// Set when the last music couldn't be loaded, so it counts as
+        /// finished and the playlist moves on
+        music_failed: bool,
+        music_volume: f32,
+        music_tempo: f32,
+        effects: HashMap<Sfx, audio::Wav>,
+        sfx_volume: f32,
+    }
+
+    impl SoloudAudio {
+        /// Returns `None` if there is no audio device to play on
+        pub fn new() -> Option<Self> {
+            Some(SoloudAudio {
+                sl: Soloud::default().ok()?,
+                music: audio::Wav::default(),
+                music_voice: None,
+                music_failed: false,
+                music_volume: 1.0,
+                music_tempo: 1.0,
+                effects: HashMap::new(),
+                sfx_volume: 1.0,
+            })
+        }
+    }
+
+    impl AudioBackend for SoloudAudio {
+        fn play_music(&mut self, path: &Path, looping: bool) {
+            if let Some(voice) = self.music_voice.take() {
+                self.sl.stop(voice);
+            }
+            self.music_failed = self.music.load(path).is_err();
+            if self.music_failed {
+                return;
+            }
+            self.music.set_looping(looping);
+            self.music.set_volume(self.music_volume);
+            let voice = self.sl.play(&self.music);
+            self.sl
+                .set_relative_play_speed(voice, self.music_tempo)
+                .ok();
+            self.music_voice = Some(voice);
+        }
+
+        fn music_finished(&self) -> bool {
+            self.music_failed
+                || self
+                    .music_voice
+                    .is_some_and(|voice| !self.sl.is_valid_voice_handle(voice))
         }
-        self.playing = true;
-        let file_path = Path::new(&self.sound_file);
-        self.wav.load(file_path).unwrap();
-        while self.playing {
-            self.sl.play(&self.wav);
-            while self.sl.voice_count() > 0 {
-                std::thread::sleep(std::time::Duration::from_millis(100));
-                if self.rx.try_recv().is_ok() {
-                    self.sl.stop_all();
-                    self.playing = false;
-                    break;
+
+        fn load_effects(&mut self, pack: Option<&Path>) {
+            self.effects.clear();
+            for sfx in Sfx::ALL {
+                let mut wav = audio::Wav::default();
+                let loaded = match pack.and_then(|dir| find_effect(dir, sfx)) {
+                    Some(path) => wav.load(&path).is_ok(),
+                    None => false,
+                };
+                if loaded || wav.load_mem(&synth::wav(sfx)).is_ok() {
+                    wav.set_volume(self.sfx_volume);
+                    self.effects.insert(sfx, wav);
                 }
             }
         }
-        self.playing = false;
+
+        fn play_effect(&mut self, sfx: Sfx) {
+            if let Some(wav) = self.effects.get(&sfx) {
+                self.sl.play(wav);
+            }
+        }
+
+        fn set_tempo(&mut self, tempo: f32) {
+            self.music_tempo = tempo;
+            if let Some(voice) = self.music_voice {
+                self.sl.set_relative_play_speed(voice, tempo).ok();
+            }
+        }
+
+        fn set_volume(&mut self, music: f32, sfx: f32) {
+            self.music_volume = music;
+            self.music.set_volume(music);
+            if let Some(voice) = self.music_voice {
+                self.sl.set_volume(voice, music);
+            }
+            self.sfx_volume = sfx;
+            for wav in self.effects.values_mut() {
+                wav.set_volume(sfx);
+            }
+        }
+
+        fn stop(&mut self) {
+            self.sl.stop_all();
+            self.music_voice = None;
+            self.music_failed = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume() {
+        let mut volume = Volume::default();
+        assert!(volume.apply(AudioCommand::MusicVolumeUp));
+        assert_eq!(volume.music, VOLUME_STEPS);
+        volume.apply(AudioCommand::MusicVolumeDown);
+        volume.apply(AudioCommand::SfxVolumeDown);
+        volume.apply(AudioCommand::SfxVolumeDown);
+        assert_eq!(volume.music_gain(), 0.9);
+        assert_eq!(volume.sfx_gain(), 0.8);
+        volume.apply(AudioCommand::ToggleMute);
+        assert_eq!((volume.music_gain(), volume.sfx_gain()), (0.0, 0.0));
+        assert!(!volume.apply(AudioCommand::NextTrack));
+        volume.apply(AudioCommand::ToggleMute);
+        assert_eq!(volume.to_string(), "Music: 9/10 | Effects: 8/10");
+    }
+
+    #[test]
+    fn test_tempo() {
+        assert_eq!(tempo(1, 0), 1.0);
+        assert_eq!(tempo(6, 0), 1.1);
+        assert_eq!(tempo(100, 0), MAX_LEVEL_TEMPO);
+        assert_eq!(tempo(1, DANGER_HEIGHT), DANGER_TEMPO);
+        assert!(tempo(6, DANGER_HEIGHT) > tempo(6, DANGER_HEIGHT - 1));
+    }
+
+    #[cfg(feature = "sound")]
+    #[test]
+    fn test_unloadable_track() {
+        use super::super::playlist::Playlist;
+        // Needs an audio device to play on
+        let Some(mut audio) = soloud_backend::SoloudAudio::new() else {
+            return;
+        };
+        let tracks = vec!["no such track.ogg".into(), "nor this one.ogg".into()];
+        let mut playlist = Playlist::new(tracks, false, false, 1);
+        let mut played = 0;
+        let mut track = playlist.current().map(Path::to_path_buf);
+        while let Some(path) = track {
+            audio.play_music(&path, false);
+            // Counts as finished so the next track is played
+            assert!(audio.music_finished());
+            played += 1;
+            track = playlist.next_track().map(Path::to_path_buf);
+        }
+        assert_eq!(played, 2);
+    }
+
+    #[test]
+    fn test_find_effect_fallback() {
+        let dir = std::env::temp_dir().join(format!("tetris-sounds-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("single.wav"), b"").unwrap();
+        std::fs::write(dir.join("triple.ogg"), b"").unwrap();
+
+        assert_eq!(find_effect(&dir, Sfx::Single), Some(dir.join("single.wav")));
+        assert_eq!(find_effect(&dir, Sfx::Double), Some(dir.join("single.wav")));
+        assert_eq!(find_effect(&dir, Sfx::Tetris), Some(dir.join("triple.ogg")));
+        assert_eq!(find_effect(&dir, Sfx::Lock), None);
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
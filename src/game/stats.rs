@@ -0,0 +1,79 @@
+//! Stats gathered while a game is played.
+
+use super::shapes::ShapeType;
+
+/// Garbage lines sent for clearing 1 to 4 lines at once
+const ATTACK: [usize; 4] = [0, 1, 2, 4];
+
+/// Running statistics for a game. Score, lines, level, pieces and time are
+/// kept on `Game` itself.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Stats {
+    /// Inputs that moved, rotated, dropped or held a shape
+    pub keys: usize,
+    /// Singles, doubles, triples and tetrises
+    pub clears: [usize; 4],
+    /// T-spins, with or without a clear
+    pub t_spins: usize,
+    /// Shapes locked in a row that each cleared lines
+    pub combo: usize,
+    /// Longest combo so far
+    pub max_combo: usize,
+    /// Tetrises and T-spin clears in a row, without other clears between
+    pub back_to_back: usize,
+    /// Garbage lines the clears would send in a versus game
+    pub attack: usize,
+    /// Shapes locked, indexed by `ShapeType as usize - 1`
+    pub distribution: [usize; 7],
+}
+
+impl Stats {
+    /// Records a shape being locked and the lines it cleared
+    pub fn record_lock(&mut self, shape_type: ShapeType, lines: usize, t_spin: bool) {
+        self.distribution[shape_type as usize - 1] += 1;
+        if t_spin {
+            self.t_spins += 1;
+        }
+        if lines == 0 {
+            self.combo = 0;
+            return;
+        }
+        self.clears[lines.min(4) - 1] += 1;
+        self.combo += 1;
+        self.max_combo = self.max_combo.max(self.combo);
+        self.back_to_back = if lines >= 4 || t_spin {
+            self.back_to_back + 1
+        } else {
+            0
+        };
+        // T-spins send two lines for every line cleared
+        self.attack += if t_spin {
+            lines * 2
+        } else {
+            ATTACK[lines.min(4) - 1]
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_lock() {
+        let mut stats = Stats::default();
+        stats.record_lock(ShapeType::I, 4, false);
+        stats.record_lock(ShapeType::T, 2, true);
+        assert_eq!(stats.back_to_back, 2);
+        stats.record_lock(ShapeType::O, 0, false);
+        assert_eq!(stats.back_to_back, 2);
+        stats.record_lock(ShapeType::L, 1, false);
+        assert_eq!(stats.clears, [1, 1, 0, 1]);
+        assert_eq!(stats.t_spins, 1);
+        assert_eq!(stats.combo, 1);
+        assert_eq!(stats.max_combo, 2);
+        assert_eq!(stats.back_to_back, 0);
+        assert_eq!(stats.attack, 8);
+        assert_eq!(stats.distribution, [1, 0, 1, 1, 0, 1, 0]);
+    }
+}
@@ -0,0 +1,148 @@
+//! A small square wave and noise synth for the built-in sound effects, so
+//! the game has sound without any asset files.
+
+use super::sound::Sfx;
+
+/// Samples per second of the synthesized effects
+pub const SAMPLE_RATE: u32 = 22050;
+const VOLUME: f32 = 0.4;
+/// Samples taken to fade a tone in, to avoid clicks
+const ATTACK: f32 = 64.0;
+
+#[derive(Copy, Clone)]
+enum Wave {
+    Square,
+    Noise,
+}
+
+/// A tone sweeping linearly from one frequency to another and fading out
+struct Tone {
+    wave: Wave,
+    from: f32,
+    to: f32,
+    seconds: f32,
+}
+
+const fn square(from: f32, to: f32, seconds: f32) -> Tone {
+    Tone {
+        wave: Wave::Square,
+        from,
+        to,
+        seconds,
+    }
+}
+
+const fn noise(seconds: f32) -> Tone {
+    Tone {
+        wave: Wave::Noise,
+        from: 0.0,
+        to: 0.0,
+        seconds,
+    }
+}
+
+/// Notes of a rising arpeggio, each played for `seconds`
+fn arpeggio(notes: &[f32], seconds: f32) -> Vec<Tone> {
+    notes.iter().map(|&f| square(f, f, seconds)).collect()
+}
+
+/// The tones making up each effect, played one after another
+fn tones(sfx: Sfx) -> Vec<Tone> {
+    match sfx {
+        Sfx::Move => vec![square(220.0, 220.0, 0.03)],
+        Sfx::Rotate => vec![square(440.0, 660.0, 0.05)],
+        Sfx::Lock => vec![noise(0.04), square(110.0, 70.0, 0.06)],
+        Sfx::Single => arpeggio(&[523.3, 659.3], 0.07),
+        Sfx::Double => arpeggio(&[523.3, 659.3, 784.0], 0.07),
+        Sfx::Triple => arpeggio(&[523.3, 659.3, 784.0, 1046.5], 0.07),
+        Sfx::Tetris => {
+            let mut tones = arpeggio(&[523.3, 659.3, 784.0, 1046.5], 0.06);
+            tones.push(square(1318.5, 1318.5, 0.25));
+            tones
+        }
+        Sfx::TSpin => vec![square(300.0, 900.0, 0.15)],
+        Sfx::Hold => vec![square(330.0, 440.0, 0.06)],
+        Sfx::LevelUp => arpeggio(&[392.0, 523.3, 659.3, 784.0], 0.1),
+        Sfx::GameOver => vec![square(440.0, 110.0, 0.6), noise(0.2)],
+    }
+}
+
+/// Renders an effect as mono samples between -1 and 1
+pub fn samples(sfx: Sfx) -> Vec<f32> {
+    let mut samples = Vec::new();
+    let mut phase = 0.0_f32;
+    // xorshift32, seeded the same every time so effects always sound alike
+    let mut noise_state: u32 = 0x9e37_79b9;
+    for tone in tones(sfx) {
+        let length = (tone.seconds * SAMPLE_RATE as f32) as usize;
+        for i in 0..length {
+            let t = i as f32 / length as f32;
+            let freq = tone.from + (tone.to - tone.from) * t;
+            phase = (phase + freq / SAMPLE_RATE as f32).fract();
+            let value = match tone.wave {
+                Wave::Square if phase < 0.5 => 1.0,
+                Wave::Square => -1.0,
+                Wave::Noise => {
+                    noise_state ^= noise_state << 13;
+                    noise_state ^= noise_state >> 17;
+                    noise_state ^= noise_state << 5;
+                    noise_state as f32 / u32::MAX as f32 * 2.0 - 1.0
+                }
+            };
+            let envelope = (i as f32 / ATTACK).min(1.0) * (1.0 - t);
+            samples.push(value * envelope * VOLUME);
+        }
+    }
+    samples
+}
+
+/// Renders an effect as a 16 bit mono PCM WAV file
+pub fn wav(sfx: Sfx) -> Vec<u8> {
+    let samples = samples(sfx);
+    let data_len = samples.len() as u32 * 2;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // Mono
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&((sample * i16::MAX as f32) as i16).to_le_bytes());
+    }
+    wav
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples() {
+        for sfx in Sfx::ALL {
+            let samples = samples(sfx);
+            assert!(!samples.is_empty(), "{:?} is silent", sfx);
+            assert!(samples.iter().all(|s| s.abs() <= VOLUME));
+        }
+        assert_eq!(samples(Sfx::Lock), samples(Sfx::Lock));
+    }
+
+    #[test]
+    fn test_wav() {
+        let wav = wav(Sfx::Move);
+        let samples = samples(Sfx::Move).len();
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(wav.len(), 44 + samples * 2);
+        assert_eq!(
+            u32::from_le_bytes(wav[40..44].try_into().unwrap()),
+            samples as u32 * 2
+        );
+    }
+}
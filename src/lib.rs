@@ -1,116 +1,33 @@
-mod game;
-mod tui;
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
-use crossterm::{event, terminal};
-use game::sound::Player;
-use std::path::Path;
-use std::time::Duration;
-use tokio::sync::mpsc::{self, Sender};
-use tui::Tui;
-
-const TICK_RATE: u64 = 250;
-const SOUND_FILE: &str = "soundtrack.mp3";
+//! A terminal Tetris game and the engine behind it.
+//!
+//! The [`game`] module is a deterministic simulation with no terminal or
+//! audio dependencies. Build with `default-features = false` to use it on
+//! its own, for example to drive games from a bot:
+//!
+//! ```
+//! use tetris::{Action, Game, Mode, Move};
+//!
+//! let mut game = Game::with_seed(Mode::Marathon, 42);
+//! while game.pieces < 10 && !game.game_over {
+//!     game.update();
+//!     game.apply(Action::Move(Move::Left));
+//!     game.apply(Action::Drop);
+//! }
+//! assert_eq!(game.pieces, 10);
+//! let filled = game.board_ref().iter().flatten().filter(|&&c| c != 0).count();
+//! assert!(filled > 0);
+//! ```
+//!
+//! Features:
+//! - `tui` (default): the crossterm front end and the `tetris` binary
+//! - `sound` (default): soundtrack playback through soloud
 
-pub async fn run() {
-    let (tx, rx) = mpsc::channel::<usize>(1);
-    let sound_handle = tokio::spawn(async move {
-        sound_loop(rx);
-    });
-    game_loop(tx.clone()).await;
-    tx.send(1).await.ok();
-    sound_handle.await.ok();
-}
-
-async fn game_loop(tx: Sender<usize>) {
-    let mut term = Tui::new();
-    let mut game = game::Game::new();
-    // game.spawn(ShapeType::I, 5, 5);
-    terminal::enable_raw_mode().unwrap();
-    loop {
-        if event::poll(Duration::from_millis(TICK_RATE)).unwrap() {
-            match event::read().unwrap() {
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('q'),
-                    modifiers: KeyModifiers::NONE,
-                    kind: KeyEventKind::Press,
-                    ..
-                }) => {
-                    break;
-                }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Left,
-                    modifiers: KeyModifiers::NONE,
-                    kind: KeyEventKind::Press,
-                    ..
-                }) => {
-                    game.move_shape(game::Move::Left);
-                }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Right,
-                    modifiers: KeyModifiers::NONE,
-                    kind: KeyEventKind::Press,
-                    ..
-                }) => {
-                    game.move_shape(game::Move::Right);
-                }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Down,
-                    modifiers: KeyModifiers::NONE,
-                    kind: KeyEventKind::Press,
-                    ..
-                }) => {
-                    game.move_shape(game::Move::Down);
-                }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Up,
-                    modifiers: KeyModifiers::NONE,
-                    kind: KeyEventKind::Press,
-                    ..
-                }) => {
-                    game.move_shape(game::Move::Rotate);
-                }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char(' '),
-                    modifiers: KeyModifiers::NONE,
-                    kind: KeyEventKind::Press,
-                    ..
-                }) => {
-                    game.drop_shape();
-                }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('p'),
-                    modifiers: KeyModifiers::NONE,
-                    kind: KeyEventKind::Press,
-                    ..
-                }) => {
-                    game.paused = !game.paused;
-                }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('d'),
-                    modifiers: KeyModifiers::NONE,
-                    kind: KeyEventKind::Press,
-                    ..
-                }) => {
-                    tx.send(1).await.ok();
-                }
-                _ => {}
-            }
-        }
-        game.update();
-        term.draw_board(game.board_ref(), game.preview_board_ref(), game.score);
-        if game.game_over {
-            break;
-        }
-    }
-    terminal::disable_raw_mode().unwrap();
-}
+pub mod game;
+#[cfg(feature = "tui")]
+mod tui;
 
-fn sound_loop(rx: mpsc::Receiver<usize>) {
-    let mut player = match Path::new(SOUND_FILE).exists() {
-        true => Some(Player::new(SOUND_FILE, rx)),
-        false => None,
-    };
-    if let Some(player) = &mut player {
-        player.play();
-    }
-}
+pub use game::mode::Mode;
+pub use game::shapes::{Shape, ShapeType};
+pub use game::{Action, Game, Move};
+#[cfg(feature = "tui")]
+pub use tui::run;
@@ -0,0 +1,170 @@
+use crate::game::{Event, Game, FPS, WIDTH};
+use std::time::{Duration, Instant};
+
+/// Frames a newly locked shape is drawn highlighted for
+pub const LOCK_FLASH: u64 = 6;
+/// How long callouts stay next to the board
+pub const CALLOUT_TIME: Duration = Duration::from_millis(1200);
+/// Rows a callout rises while it's shown
+pub const CALLOUT_RISE: usize = 2;
+/// How long rows cleared without a line clear delay are animated for
+const CLEAR_TIME: Duration = Duration::from_millis(20 * 1000 / FPS);
+/// Part of the line clear delay the cleared rows flash for, before they
+/// collapse
+const FLASH_PART: f64 = 0.6;
+/// Flashes shown while the rows flash
+const FLASHES: f64 = 3.0;
+
+/// How a row being cleared is drawn at a point in the line clear delay
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ClearFrame {
+    /// The whole row lit up
+    Flash,
+    /// The row as it is
+    Normal,
+    /// The row emptying from the middle outwards, with the number of
+    /// columns gone
+    Collapse(usize),
+}
+
+impl ClearFrame {
+    /// The frame for `progress` through the line clear delay, from 0 to 1
+    pub fn at(progress: f64) -> ClearFrame {
+        if progress < FLASH_PART {
+            // On for the first half of each flash
+            if (progress / FLASH_PART * FLASHES).fract() < 0.5 {
+                ClearFrame::Flash
+            } else {
+                ClearFrame::Normal
+            }
+        } else {
+            let collapsed = (progress - FLASH_PART) / (1.0 - FLASH_PART);
+            // Columns go in pairs, one either side of the middle
+            let pairs = (collapsed * (WIDTH / 2) as f64).ceil() as usize;
+            ClearFrame::Collapse((pairs * 2).min(WIDTH))
+        }
+    }
+
+    /// Whether column `x` is still drawn
+    pub fn shows(self, x: usize) -> bool {
+        match self {
+            ClearFrame::Collapse(gone) => {
+                let from = (WIDTH - gone) / 2;
+                x < from || x >= from + gone
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Rows cleared in a mode without a line clear delay. They're already
+/// gone from the board, so they're drawn over it as they were.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ClearAnimation {
+    pub rows: Vec<(usize, [u8; WIDTH])>,
+    pub shown: Instant,
+}
+
+impl ClearAnimation {
+    /// How far through the animation it is, from 0 to 1
+    pub fn progress(&self) -> f64 {
+        (self.shown.elapsed().as_secs_f64() / CLEAR_TIME.as_secs_f64()).min(1.0)
+    }
+
+    pub fn expired(&self) -> bool {
+        self.shown.elapsed() > CLEAR_TIME
+    }
+}
+
+/// Text shown beside the board for a notable clear
+#[derive(Debug, PartialEq, Clone)]
+pub struct Callout {
+    pub text: String,
+    pub shown: Instant,
+}
+
+impl Callout {
+    /// Rows the callout has risen by so far
+    pub fn rise(&self) -> usize {
+        let age = self.shown.elapsed().as_secs_f64() / CALLOUT_TIME.as_secs_f64();
+        ((age * (CALLOUT_RISE + 1) as f64) as usize).min(CALLOUT_RISE)
+    }
+
+    pub fn expired(&self) -> bool {
+        self.shown.elapsed() > CALLOUT_TIME
+    }
+}
+
+/// Callout texts for the clear described by `events`, read along with the
+/// game's stats right after it
+pub fn callout_texts(events: &[Event], game: &Game) -> Vec<String> {
+    let lines = events
+        .iter()
+        .find_map(|event| match event {
+            Event::LineClear(lines) => Some(*lines),
+            _ => None,
+        })
+        .unwrap_or(0);
+    let mut texts = Vec::new();
+    match (events.contains(&Event::TSpin), lines) {
+        (true, 0) => texts.push("T-SPIN".to_string()),
+        (true, _) => texts.push(format!(
+            "T-SPIN {}",
+            ["SINGLE", "DOUBLE", "TRIPLE"][lines.min(3) - 1]
+        )),
+        (false, 4..) => texts.push("TETRIS".to_string()),
+        _ => {}
+    }
+    // Chains only carry on with a clear
+    if lines == 0 {
+        return texts;
+    }
+    if game.stats.back_to_back > 1 {
+        texts.push(format!("B2B ×{}", game.stats.back_to_back - 1));
+    }
+    if game.stats.combo > 1 {
+        texts.push(format!("COMBO {}", game.stats.combo - 1));
+    }
+    texts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clear_frame() {
+        assert_eq!(ClearFrame::at(0.0), ClearFrame::Flash);
+        assert_eq!(ClearFrame::at(0.15), ClearFrame::Normal);
+        assert_eq!(ClearFrame::at(FLASH_PART), ClearFrame::Collapse(0));
+        assert_eq!(ClearFrame::at(1.0), ClearFrame::Collapse(WIDTH));
+        let half = ClearFrame::at(0.75);
+        assert_eq!(half, ClearFrame::Collapse(WIDTH / 2));
+        assert!(half.shows(0));
+        assert!(!half.shows(WIDTH / 2));
+        assert!(half.shows(WIDTH - 1));
+    }
+
+    #[test]
+    fn test_callout_texts() {
+        let mut game = Game::new();
+        assert!(callout_texts(&[Event::Lock], &game).is_empty());
+        assert_eq!(
+            callout_texts(&[Event::Lock, Event::TSpin], &game),
+            ["T-SPIN"]
+        );
+        assert!(callout_texts(&[Event::LineClear(2)], &game).is_empty());
+        game.stats.combo = 6;
+        game.stats.back_to_back = 4;
+        assert_eq!(
+            callout_texts(&[Event::Lock, Event::LineClear(4)], &game),
+            ["TETRIS", "B2B ×3", "COMBO 5"]
+        );
+        game.stats.combo = 1;
+        game.stats.back_to_back = 1;
+        assert_eq!(
+            callout_texts(&[Event::TSpin, Event::LineClear(2)], &game),
+            ["T-SPIN DOUBLE"]
+        );
+    }
+}
@@ -0,0 +1,646 @@
+use super::guard::TerminalGuard;
+use super::keys;
+use super::menu::{MenuChoice, PauseMenu, Setting};
+use super::panel::PanelItem;
+use super::settings::Settings;
+use super::theme::{self, Theme};
+use super::Tui;
+use crate::game::history::History;
+use crate::game::mode::Mode;
+use crate::game::playlist::{track_name, Playlist};
+use crate::game::replay::{Playback, Replay};
+use crate::game::shapes::ShapeType;
+use crate::game::sound::{self, AudioBackend, AudioCommand, Sfx};
+use crate::game::{Action, Game, Move, FPS};
+use crossterm::event;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, error::TryRecvError, Sender};
+
+const TICK_RATE: u64 = 250;
+/// Playback speeds for the replay viewer
+const REPLAY_SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+/// Frames skipped by one seek in the replay viewer
+const REPLAY_SEEK: u64 = 5 * FPS;
+/// Played when the music directory has no tracks
+const SOUND_FILE: &str = "soundtrack.mp3";
+/// How often the sound thread checks whether a track has ended
+const MUSIC_POLL: Duration = Duration::from_millis(10);
+const SAVE_FILE: &str = "tetris.save";
+/// Where a save that can't be loaded is moved to
+const BAD_SAVE_FILE: &str = "tetris.save.bad";
+const REPLAY_DIR: &str = "replays";
+const SETTINGS_FILE: &str = "tetris.settings";
+const THEME_DIR: &str = "themes";
+
+/// Runs the `tetris` binary: plays a game, or plays back or verifies a
+/// replay, depending on the command line
+pub async fn run() {
+    let (flags, args): (Vec<String>, Vec<String>) =
+        std::env::args().partition(|arg| arg.starts_with("--"));
+    // Overrides the ascii setting
+    let ascii = flags.iter().rev().find_map(|flag| match flag.as_str() {
+        "--ascii" => Some(true),
+        "--unicode" => Some(false),
+        _ => None,
+    });
+    if args.get(1).map(String::as_str) == Some("replay") {
+        match args.get(2) {
+            Some(path) => replay_loop(Path::new(path), ascii),
+            None => eprintln!("Usage: tetris replay <file>"),
+        }
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("verify") {
+        match args.get(2) {
+            Some(path) => verify(Path::new(path)),
+            None => eprintln!("Usage: tetris verify <file>"),
+        }
+        return;
+    }
+    let mode = args
+        .get(1)
+        .and_then(|name| Mode::from_name(name))
+        .unwrap_or(Mode::Marathon);
+    let settings = Settings::load(Path::new(SETTINGS_FILE));
+    let sound_settings = settings.clone();
+    let (tx, rx) = mpsc::channel::<AudioCommand>(16);
+    let (playing_tx, playing) = std::sync::mpsc::channel();
+    let sound_handle =
+        tokio::task::spawn_blocking(move || sound_loop(rx, &sound_settings, playing_tx));
+    game_loop(mode, settings, ascii, tx.clone(), playing).await;
+    tx.send(AudioCommand::Stop).await.ok();
+    sound_handle.await.ok();
+}
+
+/// Creates the terminal UI as the settings ask for, with `ascii` from the
+/// command line overriding the ascii setting
+fn new_tui(settings: &Settings, ascii: Option<bool>) -> Tui {
+    let mut term = Tui::new();
+    term.panel = settings.stats_panel.clone();
+    term.animations = settings.animations;
+    term.keys = settings.keys;
+    term.cell_size = settings.cell_size;
+    let ascii = ascii.or(settings.ascii).unwrap_or_else(theme::detect_ascii);
+    term.set_theme(Theme::load(&settings.theme, Path::new(THEME_DIR)), ascii);
+    term
+}
+
+async fn game_loop(
+    mode: Mode,
+    mut settings: Settings,
+    ascii: Option<bool>,
+    tx: Sender<AudioCommand>,
+    playing: std::sync::mpsc::Receiver<String>,
+) {
+    let mut term = new_tui(&settings, ascii);
+    if !settings.warnings.is_empty() {
+        term.notify(settings.warnings.join(" | "));
+    }
+    let guard = TerminalGuard::new().unwrap();
+    let saved = if Path::new(SAVE_FILE).exists() {
+        continue_saved(&mut term)
+    } else {
+        None
+    };
+    // Only games played from the start can be replayed, and undo in
+    // practice modes can't be
+    let mut replay = None;
+    let mut game = saved.unwrap_or_else(|| {
+        let game = Game::with_mode(mode);
+        if !mode.endless() {
+            replay = Some(Replay::new(&game));
+        }
+        game
+    });
+    let mut history = History::new();
+    let mut tempo = 1.0;
+    // Set while the game is paused because the terminal is too small
+    let mut auto_paused = false;
+    let mut menu = game.paused.then(PauseMenu::new);
+    let frame_time = Duration::from_nanos(1_000_000_000 / FPS);
+    let mut clock = Instant::now();
+    // game.spawn(ShapeType::I, 5, 5);
+    loop {
+        // Practice modes keep a copy from before each placement for undo
+        let mut before = game.mode.endless().then(|| game.clone());
+        let mut action = None;
+        let mut audio = None;
+        let mut choice = None;
+        // Animations and 20G shapes are drawn every frame, the rest only
+        // when something happens or every tick
+        let timeout = if !game.paused && (term.animating(&game) || game.mode.twenty_g()) {
+            frame_time.saturating_sub(clock.elapsed())
+        } else {
+            Duration::from_millis(TICK_RATE)
+        };
+        if event::poll(timeout).unwrap() {
+            match event::read().unwrap() {
+                Event::Key(KeyEvent {
+                    code,
+                    modifiers: KeyModifiers::NONE,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) if menu.is_some() => {
+                    choice = menu
+                        .as_mut()
+                        .and_then(|menu| menu.key(code, &settings.keys));
+                }
+                Event::Key(KeyEvent {
+                    code,
+                    modifiers: KeyModifiers::NONE,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) if code == settings.keys.quit => {
+                    action = (!game.paused).then_some(Action::Pause);
+                    menu = Some(PauseMenu::confirm_quit());
+                }
+                Event::Key(KeyEvent {
+                    code,
+                    modifiers: KeyModifiers::NONE,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) if code == settings.keys.restart => {
+                    choice = Some(MenuChoice::Restart);
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('c'),
+                    modifiers: KeyModifiers::CONTROL,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => {
+                    break;
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Left,
+                    modifiers: KeyModifiers::NONE,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => {
+                    action = Some(Action::Move(Move::Left));
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Right,
+                    modifiers: KeyModifiers::NONE,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => {
+                    action = Some(Action::Move(Move::Right));
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Down,
+                    modifiers: KeyModifiers::NONE,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => {
+                    action = Some(Action::Move(Move::Down));
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Up,
+                    modifiers: KeyModifiers::NONE,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => {
+                    action = Some(Action::Move(Move::Rotate));
+                }
+                Event::Key(KeyEvent {
+                    code: keys::DROP,
+                    modifiers: KeyModifiers::NONE,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => {
+                    action = Some(Action::Drop);
+                }
+                Event::Key(KeyEvent {
+                    code: keys::HOLD,
+                    modifiers: KeyModifiers::NONE,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => {
+                    action = Some(Action::Hold);
+                }
+                Event::Key(KeyEvent {
+                    code: keys::GRAVITY,
+                    modifiers: KeyModifiers::NONE,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => {
+                    action = Some(Action::ToggleGravity);
+                }
+                Event::Key(KeyEvent {
+                    code: keys::UNDO,
+                    modifiers: KeyModifiers::NONE,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) if game.mode.endless() => {
+                    history.undo(&mut game);
+                    before = None;
+                }
+                Event::Key(KeyEvent {
+                    code: keys::REDO,
+                    modifiers: KeyModifiers::NONE,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) if game.mode.endless() => {
+                    history.redo(&mut game);
+                    before = None;
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char(c @ '1'..='7'),
+                    modifiers: KeyModifiers::NONE,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => {
+                    action = ShapeType::from_u8(c as u8 - b'0').map(Action::ChooseNext);
+                }
+                Event::Key(KeyEvent {
+                    code: keys::PAUSE,
+                    modifiers: KeyModifiers::NONE,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => {
+                    action = (!game.paused).then_some(Action::Pause);
+                    menu = Some(PauseMenu::new());
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char(c),
+                    modifiers: KeyModifiers::NONE,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => {
+                    audio = audio_command(c);
+                }
+                Event::FocusLost if !game.paused => {
+                    action = Some(Action::Pause);
+                    menu = Some(PauseMenu::new());
+                }
+                Event::Resize(width, height) => term.resize(width, height),
+                _ => {}
+            }
+        }
+        match choice {
+            Some(MenuChoice::Resume) => {
+                action = Some(Action::Pause);
+                menu = None;
+            }
+            Some(MenuChoice::Restart) => {
+                // A fresh game in the same mode, which can be replayed even
+                // if the one before was resumed from a save
+                game = Game::with_mode(game.mode);
+                replay = (!game.mode.endless()).then(|| Replay::new(&game));
+                history = History::new();
+                before = None;
+                menu = None;
+            }
+            Some(MenuChoice::Quit) => break,
+            Some(MenuChoice::Change(setting)) => {
+                audio = change_setting(setting, &mut settings, &mut term);
+            }
+            None => {}
+        }
+        // Pause while the terminal is too small to show the game, and carry
+        // on once it's big enough again. A menu opened meanwhile takes over,
+        // and the game stays paused until it's resumed from there.
+        if menu.is_some() {
+            auto_paused = false;
+        }
+        if action.is_none() {
+            if !term.fits() && !game.paused {
+                action = Some(Action::Pause);
+                auto_paused = true;
+            } else if term.fits() && auto_paused {
+                auto_paused = false;
+                action = game.paused.then_some(Action::Pause);
+            }
+        }
+        if let Some(command) = audio {
+            if settings.volume.apply(command) {
+                term.notify(settings.volume.to_string());
+                save_settings(&settings);
+            }
+            tx.send(command).await.ok();
+        }
+        if let Some(action) = action {
+            game.apply(action);
+            if let Some(replay) = &mut replay {
+                replay.record(game.frame(), action);
+            }
+        }
+        // Catch the game up with the time that has passed
+        while clock.elapsed() >= frame_time {
+            clock += frame_time;
+            game.update();
+        }
+        let events = game.take_events();
+        term.announce(&events, &game);
+        for event in events {
+            // Effects are dropped rather than holding up the game if the
+            // sound thread falls behind
+            tx.try_send(AudioCommand::Effect(Sfx::for_event(event)))
+                .ok();
+        }
+        let new_tempo = sound::tempo(game.level(), game.stack_height());
+        if new_tempo != tempo {
+            tempo = new_tempo;
+            tx.send(AudioCommand::Tempo(tempo)).await.ok();
+        }
+        if let Some(before) = before {
+            history.record(before, &game);
+        }
+        if let Some(track) = playing.try_iter().last() {
+            term.track = Some(track);
+        }
+        match &menu {
+            Some(menu) => {
+                let (title, items, selected) = menu.lines(&settings);
+                term.draw_paused(&game, title, &items, selected);
+            }
+            None => term.draw_board(&game),
+        }
+        if game.game_over {
+            break;
+        }
+    }
+    drop(guard);
+    if !game.game_over {
+        if let Err(e) = game.save(Path::new(SAVE_FILE)) {
+            eprintln!("Could not save game: {}", e);
+        }
+    } else if let Some(mut replay) = replay {
+        replay.finish(&game);
+        save_personal_best(&replay);
+    }
+}
+
+/// Changes a setting picked in the pause menu, applies it and saves the
+/// settings. Sound is changed through the audio command returned.
+fn change_setting(
+    setting: Setting,
+    settings: &mut Settings,
+    term: &mut Tui,
+) -> Option<AudioCommand> {
+    match setting {
+        Setting::Theme => {
+            // Cycles through the built in themes
+            let next = Theme::BUILT_IN
+                .iter()
+                .position(|&name| name == settings.theme)
+                .map_or(0, |i| (i + 1) % Theme::BUILT_IN.len());
+            settings.theme = Theme::BUILT_IN[next].to_string();
+            let ascii = term.ascii();
+            term.set_theme(Theme::load(&settings.theme, Path::new(THEME_DIR)), ascii);
+        }
+        Setting::Animations => {
+            settings.animations = !settings.animations;
+            term.animations = settings.animations;
+        }
+        Setting::StatsPanel => {
+            settings.stats_panel = if settings.stats_panel.is_empty() {
+                PanelItem::ALL.to_vec()
+            } else {
+                Vec::new()
+            };
+            term.panel = settings.stats_panel.clone();
+        }
+        Setting::Sound => return Some(AudioCommand::ToggleMute),
+    }
+    save_settings(settings);
+    None
+}
+
+/// Writes the settings file. Settings that can't be written just aren't
+/// kept.
+fn save_settings(settings: &Settings) {
+    settings.save(Path::new(SETTINGS_FILE)).ok();
+}
+
+/// Plays a recorded game back with pause, seeking, frame stepping and speed
+/// control
+fn replay_loop(path: &Path, ascii: Option<bool>) {
+    let replay = match Replay::load(path) {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let mut playback = Playback::new(replay);
+    let mut term = new_tui(&Settings::load(Path::new(SETTINGS_FILE)), ascii);
+    let mut speed = 2;
+    let mut paused = false;
+    // Frames owed to the playback at the current speed
+    let mut pending = 0.0;
+    let mut clock = Instant::now();
+    let guard = TerminalGuard::new().unwrap();
+    loop {
+        if event::poll(Duration::from_millis(1000 / FPS)).unwrap() {
+            let frame = playback.game().frame();
+            match event::read().unwrap() {
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('c'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                }) => break,
+                Event::Key(KeyEvent {
+                    code,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => match code {
+                    KeyCode::Char('q') => break,
+                    KeyCode::Char('p') | KeyCode::Char(' ') => paused = !paused,
+                    KeyCode::Char('.') => {
+                        paused = true;
+                        playback.step();
+                    }
+                    KeyCode::Left => playback.seek(frame.saturating_sub(REPLAY_SEEK)),
+                    KeyCode::Right => playback.seek(frame + REPLAY_SEEK),
+                    KeyCode::Up => speed = (speed + 1).min(REPLAY_SPEEDS.len() - 1),
+                    KeyCode::Down => speed = speed.saturating_sub(1),
+                    _ => {}
+                },
+                Event::Resize(width, height) => term.resize(width, height),
+                _ => {}
+            }
+        }
+        let elapsed = clock.elapsed();
+        clock = Instant::now();
+        // Playback holds while the terminal is too small to show it
+        if !paused && term.fits() {
+            pending += elapsed.as_secs_f64() * FPS as f64 * REPLAY_SPEEDS[speed];
+            while pending >= 1.0 {
+                pending -= 1.0;
+                playback.step();
+            }
+        }
+        let game = playback.game();
+        let state = match (playback.finished(), playback.check()) {
+            (true, Ok(())) => " | Finished",
+            (true, Err(_)) => " | Diverged",
+            _ if paused => " | Paused",
+            _ => "",
+        };
+        let status = format!(
+            "Replay | Score: {} | Time: {:.1}s / {:.1}s | Speed: {}x{}",
+            game.score,
+            game.play_time().as_secs_f64(),
+            playback.replay.frames as f64 / FPS as f64,
+            REPLAY_SPEEDS[speed],
+            state
+        );
+        term.draw_replay(game, &status);
+    }
+    drop(guard);
+    // Check the result even if the viewer was closed early
+    playback.run();
+    if let Err(e) = playback.check() {
+        eprintln!("{}", e);
+    }
+}
+
+/// Plays a replay back without a terminal and prints the result. Exits with
+/// an error if the replay can't be read or doesn't give its recorded result.
+fn verify(path: &Path) {
+    let replay = match Replay::load(path) {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut playback = Playback::new(replay);
+    playback.run();
+    let game = playback.game();
+    println!("mode: {}", game.mode.name());
+    println!("score: {}", game.score);
+    println!("lines: {}", game.lines);
+    println!("time: {:.2}s", game.play_time().as_secs_f64());
+    println!("frames: {}", game.frame());
+    println!("board: {:016x}", game.board_hash());
+    if let Err(e) = playback.check() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Keeps the replay if it beats the best score recorded for its mode
+fn save_personal_best(replay: &Replay) {
+    let path = Path::new(REPLAY_DIR).join(format!("{}-best.replay", replay.mode.name()));
+    if let Ok(best) = Replay::load(&path) {
+        if best.score >= replay.score {
+            return;
+        }
+    }
+    if let Err(e) = replay.save(&path) {
+        eprintln!("Could not save replay: {}", e);
+    }
+}
+
+/// Offers to resume the game saved on the last quit. Returns `None` to
+/// start a new game instead.
+fn continue_saved(term: &mut Tui) -> Option<Game> {
+    let game = match Game::load(Path::new(SAVE_FILE)) {
+        Ok(game) => game,
+        Err(e) => {
+            // Moved aside so it isn't offered again on every launch
+            std::fs::rename(SAVE_FILE, BAD_SAVE_FILE).ok();
+            term.draw_prompt(&format!(
+                "Starting a new game: {}. It was moved to {}",
+                e, BAD_SAVE_FILE
+            ));
+            wait_for_key();
+            return None;
+        }
+    };
+    term.draw_prompt("Continue saved game? (y/n)");
+    let resume = wait_for_key() == KeyCode::Char('y');
+    std::fs::remove_file(SAVE_FILE).ok();
+    resume.then_some(game)
+}
+
+fn wait_for_key() -> KeyCode {
+    loop {
+        if let Event::Key(KeyEvent {
+            code,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event::read().unwrap()
+        {
+            return code;
+        }
+    }
+}
+
+/// Returns the audio command bound to a key
+fn audio_command(key: char) -> Option<AudioCommand> {
+    match key {
+        'd' => Some(AudioCommand::ToggleMute),
+        '=' => Some(AudioCommand::MusicVolumeUp),
+        '-' => Some(AudioCommand::MusicVolumeDown),
+        ']' => Some(AudioCommand::SfxVolumeUp),
+        '[' => Some(AudioCommand::SfxVolumeDown),
+        'n' => Some(AudioCommand::NextTrack),
+        'b' => Some(AudioCommand::PreviousTrack),
+        _ => None,
+    }
+}
+
+/// Plays the music and the effects sent on `rx` until told to stop. The
+/// name of each track is sent on `playing` as it starts.
+fn sound_loop(
+    mut rx: mpsc::Receiver<AudioCommand>,
+    settings: &Settings,
+    playing: std::sync::mpsc::Sender<String>,
+) {
+    let mut audio = sound::backend();
+    let mut volume = settings.volume;
+    audio.set_volume(volume.music_gain(), volume.sfx_gain());
+    audio.load_effects(settings.sound_pack());
+    let mut playlist = Playlist::from_dir(
+        &settings.music_dir,
+        settings.shuffle,
+        settings.repeat,
+        rand::random(),
+    );
+    if playlist.is_empty() && Path::new(SOUND_FILE).exists() {
+        playlist = Playlist::new(vec![SOUND_FILE.into()], false, settings.repeat, 0);
+    }
+    // A lone repeating track is looped by the audio library, without a gap.
+    // Other tracks are loaded and started once the last one is seen to have
+    // ended, so there is a short gap between them. Queueing them in the
+    // library instead would need every track to have the same sample rate.
+    let looping = playlist.len() == 1 && playlist.repeat;
+    let play = |audio: &mut dyn AudioBackend, track: Option<&Path>| {
+        if let Some(path) = track {
+            audio.play_music(path, looping);
+            playing.send(track_name(path)).ok();
+        }
+    };
+    play(audio.as_mut(), playlist.current());
+    loop {
+        let command = match rx.try_recv() {
+            Ok(command) => command,
+            Err(TryRecvError::Empty) => {
+                if audio.music_finished() {
+                    play(audio.as_mut(), playlist.next_track());
+                }
+                std::thread::sleep(MUSIC_POLL);
+                continue;
+            }
+            Err(TryRecvError::Disconnected) => break,
+        };
+        match command {
+            AudioCommand::Effect(sfx) => audio.play_effect(sfx),
+            AudioCommand::NextTrack => play(audio.as_mut(), playlist.next_track()),
+            AudioCommand::PreviousTrack => play(audio.as_mut(), playlist.previous_track()),
+            AudioCommand::Tempo(tempo) => audio.set_tempo(tempo),
+            AudioCommand::Stop => break,
+            command => {
+                if volume.apply(command) {
+                    audio.set_volume(volume.music_gain(), volume.sfx_gain());
+                }
+            }
+        }
+    }
+    audio.stop();
+}
@@ -0,0 +1,212 @@
+use crossterm::{
+    cursor, queue,
+    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    terminal,
+};
+use std::io::{self, Write};
+
+/// One character on screen and the colours it's drawn in. `None` is the
+/// terminal's default colour.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Cell {
+    pub symbol: char,
+    pub color: Option<Color>,
+    pub background: Option<Color>,
+}
+
+const BLANK: Cell = Cell {
+    symbol: ' ',
+    color: None,
+    background: None,
+};
+
+/// A screen's worth of cells. Each frame is drawn into a new buffer, and
+/// only the cells that differ from the last frame are sent to the terminal.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Buffer {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Buffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Buffer {
+            width,
+            height,
+            cells: vec![BLANK; width * height],
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Writes `text` on row `y` starting at column `x`, over the background
+    /// already there. Anything outside the buffer is cut off.
+    pub fn print(&mut self, x: usize, y: usize, text: &str, color: Option<Color>) {
+        if y >= self.height {
+            return;
+        }
+        for (i, symbol) in text.chars().enumerate() {
+            if x + i >= self.width {
+                break;
+            }
+            let cell = &mut self.cells[y * self.width + x + i];
+            cell.symbol = symbol;
+            cell.color = color;
+        }
+    }
+
+    /// Sets the background of a `width` by `height` area with its top left
+    /// corner at (x, y)
+    pub fn paint(&mut self, x: usize, y: usize, width: usize, height: usize, color: Color) {
+        for y in y..(y + height).min(self.height) {
+            for x in x..(x + width).min(self.width) {
+                self.cells[y * self.width + x].background = Some(color);
+            }
+        }
+    }
+
+    /// Replaces every character outside ASCII with an ASCII look-alike,
+    /// for terminals that can't show them
+    pub fn make_ascii(&mut self) {
+        for cell in &mut self.cells {
+            cell.symbol = ascii_symbol(cell.symbol);
+        }
+    }
+
+    /// Queues what has to be written to turn `previous` on screen into this
+    /// buffer. A buffer of a different size is redrawn from a clear screen.
+    pub fn draw_diff(&self, previous: &Buffer, out: &mut impl Write) -> io::Result<()> {
+        let redraw = previous.size() != self.size();
+        if redraw {
+            queue!(out, terminal::Clear(terminal::ClearType::All))?;
+        }
+        // Where the terminal's cursor and colour are, so that runs of
+        // changed cells don't repeat them
+        let mut at = None;
+        let mut pen = None;
+        for (i, &cell) in self.cells.iter().enumerate() {
+            let unchanged = if redraw {
+                cell == BLANK
+            } else {
+                cell == previous.cells[i]
+            };
+            if unchanged {
+                continue;
+            }
+            let (x, y) = (i % self.width, i / self.width);
+            if at != Some((x, y)) {
+                queue!(out, cursor::MoveTo(x as u16, y as u16))?;
+            }
+            let colors = (cell.color, cell.background);
+            if pen != Some(colors) {
+                // Resetting is the only way back to the default colours
+                match colors {
+                    (Some(color), Some(background)) => queue!(
+                        out,
+                        SetForegroundColor(color),
+                        SetBackgroundColor(background)
+                    )?,
+                    (Some(color), None) => queue!(out, ResetColor, SetForegroundColor(color))?,
+                    (None, Some(background)) => {
+                        queue!(out, ResetColor, SetBackgroundColor(background))?
+                    }
+                    (None, None) => queue!(out, ResetColor)?,
+                }
+                pen = Some(colors);
+            }
+            queue!(out, Print(cell.symbol))?;
+            at = Some((x + 1, y));
+        }
+        queue!(out, ResetColor)
+    }
+}
+
+fn ascii_symbol(symbol: char) -> char {
+    match symbol {
+        ' '..='~' => symbol,
+        '─' | '━' | '═' => '-',
+        '│' | '┃' | '║' => '|',
+        // The rest of the box drawing characters
+        '\u{2500}'..='\u{257f}' => '+',
+        // Block elements
+        '\u{2580}'..='\u{259f}' => '#',
+        '×' => 'x',
+        '♪' => '~',
+        _ => '?',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(buffer: &Buffer, previous: &Buffer) -> String {
+        let mut out = Vec::new();
+        buffer.draw_diff(previous, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_print_clips() {
+        let mut buffer = Buffer::new(4, 2);
+        buffer.print(2, 1, "abc", Some(Color::Red));
+        buffer.print(0, 2, "off screen", None);
+        assert_eq!(buffer.cells[6].symbol, 'a');
+        assert_eq!(buffer.cells[7].symbol, 'b');
+        assert_eq!(buffer.cells[7].color, Some(Color::Red));
+        assert_eq!(buffer.cells[0], BLANK);
+    }
+
+    #[test]
+    fn test_draw_diff() {
+        let mut previous = Buffer::new(10, 3);
+        previous.print(0, 0, "Score: 10", None);
+        let mut buffer = previous.clone();
+        // Nothing changed, nothing but the colour reset is written
+        assert_eq!(diff(&buffer, &previous), "\x1b[0m");
+
+        // Only the '1' changed
+        buffer.print(7, 0, "20", None);
+        assert_eq!(diff(&buffer, &previous), "\x1b[1;8H\x1b[0m2\x1b[0m");
+    }
+
+    #[test]
+    fn test_make_ascii() {
+        let mut buffer = Buffer::new(12, 1);
+        buffer.print(0, 0, "╭─│█×♪é a", None);
+        buffer.make_ascii();
+        let text = buffer
+            .cells
+            .iter()
+            .map(|cell| cell.symbol)
+            .collect::<String>();
+        assert_eq!(text, "+-|#x~? a   ");
+    }
+
+    #[test]
+    fn test_paint() {
+        let mut buffer = Buffer::new(4, 2);
+        buffer.paint(1, 1, 10, 10, Color::Blue);
+        buffer.print(0, 1, "ab", Some(Color::Red));
+        assert_eq!(buffer.cells[4].background, None);
+        assert_eq!(buffer.cells[5].symbol, 'b');
+        assert_eq!(buffer.cells[5].background, Some(Color::Blue));
+        assert_eq!(buffer.cells[7].background, Some(Color::Blue));
+        assert_eq!(
+            diff(&buffer, &Buffer::new(4, 2)).split_once("H").unwrap().1,
+            "\x1b[0m\x1b[38;5;9ma\x1b[38;5;9m\x1b[48;5;12mb\x1b[0m\x1b[48;5;12m  \x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_draw_diff_resized() {
+        let mut buffer = Buffer::new(10, 3);
+        buffer.print(0, 0, "Score", None);
+        let out = diff(&buffer, &Buffer::new(0, 0));
+        assert!(out.starts_with("\x1b[2J"));
+        assert!(out.contains("Score"));
+    }
+}
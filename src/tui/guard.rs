@@ -0,0 +1,91 @@
+use crossterm::{cursor, event, execute, terminal};
+use std::io::stdout;
+use std::sync::Once;
+
+static HANDLERS: Once = Once::new();
+
+/// Puts the terminal in raw mode on the alternate screen with the cursor
+/// hidden and focus changes reported, and puts it back the way it was when
+/// dropped. The terminal is also put back on a panic, SIGINT or SIGTERM.
+pub struct TerminalGuard;
+
+impl TerminalGuard {
+    /// Must be called from within the tokio runtime, which the signal
+    /// handler runs on
+    pub fn new() -> std::io::Result<Self> {
+        HANDLERS.call_once(|| {
+            restore_on_panic();
+            restore_on_signal();
+        });
+        terminal::enable_raw_mode()?;
+        // Created before anything else can fail, so a failure below is
+        // cleaned up on drop
+        let guard = TerminalGuard;
+        execute!(
+            stdout(),
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            event::EnableFocusChange
+        )?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+/// Leaves raw mode and the alternate screen, shows the cursor and stops
+/// focus reports. Does nothing harmful if the terminal was never set up.
+fn restore() {
+    execute!(
+        stdout(),
+        event::DisableFocusChange,
+        cursor::Show,
+        terminal::LeaveAlternateScreen
+    )
+    .ok();
+    terminal::disable_raw_mode().ok();
+}
+
+/// Restores the terminal before the panic message is printed, so that it
+/// can be read and the shell is usable afterwards
+fn restore_on_panic() {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore();
+        hook(info);
+    }));
+}
+
+/// Restores the terminal and exits when the process is interrupted or
+/// terminated
+fn restore_on_signal() {
+    tokio::spawn(async {
+        let code = wait_for_signal().await;
+        restore();
+        std::process::exit(code);
+    });
+}
+
+/// Waits for SIGINT or SIGTERM and returns the exit code for it
+#[cfg(unix)]
+async fn wait_for_signal() -> i32 {
+    use tokio::signal::unix::{signal, SignalKind};
+    let Ok(mut terminate) = signal(SignalKind::terminate()) else {
+        tokio::signal::ctrl_c().await.ok();
+        return 130;
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => 130,
+        _ = terminate.recv() => 143,
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> i32 {
+    tokio::signal::ctrl_c().await.ok();
+    130
+}
@@ -0,0 +1,155 @@
+use crossterm::event::KeyCode;
+
+/// Keys for the actions that can be rebound in the settings
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct KeyBindings {
+    /// Asks to save and quit
+    pub quit: KeyCode,
+    /// Starts a new game in the same mode straight away
+    pub restart: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            quit: KeyCode::Char('q'),
+            restart: KeyCode::Char('r'),
+        }
+    }
+}
+
+/// Keys for actions that can't be rebound
+pub const DROP: KeyCode = KeyCode::Char(' ');
+pub const HOLD: KeyCode = KeyCode::Char('c');
+pub const PAUSE: KeyCode = KeyCode::Char('p');
+pub const GRAVITY: KeyCode = KeyCode::Char('g');
+pub const UNDO: KeyCode = KeyCode::Char('u');
+pub const REDO: KeyCode = KeyCode::Char('y');
+
+/// What a key already does in a game or the pause menu, if anything. The
+/// quit and restart keys can't be bound to these.
+fn taken(key: KeyCode) -> Option<&'static str> {
+    match key {
+        DROP => Some("instant drop"),
+        HOLD => Some("hold"),
+        PAUSE => Some("pause"),
+        GRAVITY => Some("gravity"),
+        UNDO => Some("undo"),
+        REDO => Some("redo and confirming quit"),
+        KeyCode::Left | KeyCode::Right | KeyCode::Down => Some("moving"),
+        KeyCode::Up => Some("rotating"),
+        KeyCode::Char('1'..='7') => Some("picking the next shape"),
+        KeyCode::Char('n') => Some("next track and not quitting"),
+        KeyCode::Char('b' | 'd' | '=' | '-' | '[' | ']') => Some("sound"),
+        KeyCode::Enter | KeyCode::Esc => Some("the pause menu"),
+        _ => None,
+    }
+}
+
+impl KeyBindings {
+    /// Puts back the default for any key that is taken by something else,
+    /// returning why for each
+    pub fn check(&mut self) -> Vec<String> {
+        let defaults = KeyBindings::default();
+        let mut warnings = Vec::new();
+        let mut warn = |setting: &str, key: KeyCode, action: &str, default: KeyCode| {
+            warnings.push(format!(
+                "{} {} is used for {}, so {} is kept",
+                setting,
+                key_name(key),
+                action,
+                key_name(default)
+            ));
+        };
+        if let Some(action) = taken(self.quit) {
+            warn("key_quit", self.quit, action, defaults.quit);
+            self.quit = defaults.quit;
+        }
+        if let Some(action) = taken(self.restart) {
+            warn("key_restart", self.restart, action, defaults.restart);
+            self.restart = defaults.restart;
+        }
+        if self.quit == self.restart {
+            warnings.push(format!(
+                "key_quit and key_restart are both {}, so the defaults are kept",
+                key_name(self.quit)
+            ));
+            *self = defaults;
+        }
+        warnings
+    }
+}
+
+/// Reads a key name: a single character, `space`, `esc`, `enter`, `tab`,
+/// `backspace`, `delete` or `f1` to `f12`
+pub fn parse_key(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+    match name.to_lowercase().as_str() {
+        "space" => Some(KeyCode::Char(' ')),
+        "esc" => Some(KeyCode::Esc),
+        "enter" => Some(KeyCode::Enter),
+        "tab" => Some(KeyCode::Tab),
+        "backspace" => Some(KeyCode::Backspace),
+        "delete" => Some(KeyCode::Delete),
+        name => name
+            .strip_prefix('f')
+            .and_then(|n| n.parse().ok())
+            .filter(|n| (1..=12).contains(n))
+            .map(KeyCode::F),
+    }
+}
+
+/// The name `parse_key` reads back as `key`
+pub fn key_name(key: KeyCode) -> String {
+    match key {
+        KeyCode::Char(' ') => "space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Esc => "esc".to_string(),
+        KeyCode::Enter => "enter".to_string(),
+        KeyCode::Tab => "tab".to_string(),
+        KeyCode::Backspace => "backspace".to_string(),
+        KeyCode::Delete => "delete".to_string(),
+        KeyCode::F(n) => format!("f{}", n),
+        _ => "?".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key("r"), Some(KeyCode::Char('r')));
+        assert_eq!(parse_key("Space"), Some(KeyCode::Char(' ')));
+        assert_eq!(parse_key("f4"), Some(KeyCode::F(4)));
+        assert_eq!(parse_key("f13"), None);
+        assert_eq!(parse_key("shift"), None);
+        for name in ["q", "space", "esc", "enter", "backspace", "f12"] {
+            assert_eq!(parse_key(name).map(key_name).as_deref(), Some(name));
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let mut keys = KeyBindings {
+            quit: KeyCode::Char('x'),
+            restart: KeyCode::F(5),
+        };
+        assert!(keys.check().is_empty());
+        keys.quit = KeyCode::Esc;
+        keys.restart = DROP;
+        assert_eq!(keys.check().len(), 2);
+        assert_eq!(keys, KeyBindings::default());
+        // Quit on the default restart key leaves nothing free for restart
+        keys.quit = KeyCode::Char('r');
+        assert_eq!(
+            keys.check(),
+            vec!["key_quit and key_restart are both r, so the defaults are kept"]
+        );
+        assert_eq!(keys, KeyBindings::default());
+    }
+}
@@ -0,0 +1,224 @@
+use super::keys::{KeyBindings, PAUSE};
+use super::settings::Settings;
+use crossterm::event::KeyCode;
+
+/// What the player picked in the pause menu
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum MenuChoice {
+    Resume,
+    Restart,
+    Quit,
+    /// Change a setting to its next value
+    Change(Setting),
+}
+
+/// Settings that can be changed from the pause menu
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Setting {
+    Theme,
+    Animations,
+    StatsPanel,
+    Sound,
+}
+
+impl Setting {
+    const ALL: [Setting; 4] = [
+        Setting::Theme,
+        Setting::Animations,
+        Setting::StatsPanel,
+        Setting::Sound,
+    ];
+
+    fn label(self, settings: &Settings) -> String {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        match self {
+            Setting::Theme => format!("Theme: {}", settings.theme),
+            Setting::Animations => format!("Animations: {}", on_off(settings.animations)),
+            Setting::StatsPanel => {
+                format!("Stats panel: {}", on_off(!settings.stats_panel.is_empty()))
+            }
+            Setting::Sound => format!("Sound: {}", on_off(!settings.volume.muted)),
+        }
+    }
+}
+
+const MAIN_ITEMS: [&str; 4] = ["Resume", "Restart", "Settings", "Quit"];
+const CONFIRM_ITEMS: [&str; 2] = ["Save and quit", "Keep playing"];
+
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+enum Page {
+    #[default]
+    Main,
+    Settings,
+    /// Asking whether to quit, after the quit key was pressed or Quit chosen
+    ConfirmQuit,
+}
+
+/// The menu shown over the board while the game is paused
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct PauseMenu {
+    selected: usize,
+    page: Page,
+}
+
+impl PauseMenu {
+    pub fn new() -> Self {
+        PauseMenu::default()
+    }
+
+    /// A menu asking whether to quit the game
+    pub fn confirm_quit() -> Self {
+        PauseMenu {
+            selected: 0,
+            page: Page::ConfirmQuit,
+        }
+    }
+
+    /// Handles a key press, returning what was picked if anything. The quit
+    /// and restart keys are the ones bound in the settings.
+    pub fn key(&mut self, code: KeyCode, keys: &KeyBindings) -> Option<MenuChoice> {
+        let items = match self.page {
+            Page::Main => MAIN_ITEMS.len(),
+            // The settings page ends with "Back"
+            Page::Settings => Setting::ALL.len() + 1,
+            Page::ConfirmQuit => CONFIRM_ITEMS.len(),
+        };
+        match (self.page, code) {
+            (_, KeyCode::Up) => self.selected = (self.selected + items - 1) % items,
+            (_, KeyCode::Down) => self.selected = (self.selected + 1) % items,
+            (_, KeyCode::Enter | KeyCode::Char(' ')) => return self.choose(),
+            // Pressing quit again once asked confirms it
+            (Page::ConfirmQuit, code) if code == keys.quit => return Some(MenuChoice::Quit),
+            (_, code) if code == keys.quit => *self = PauseMenu::confirm_quit(),
+            (Page::Main, code) if code == keys.restart => return Some(MenuChoice::Restart),
+            (Page::Settings, KeyCode::Esc | PAUSE) => self.back(),
+            (Page::ConfirmQuit, KeyCode::Char('y')) => return Some(MenuChoice::Quit),
+            (Page::ConfirmQuit, KeyCode::Char('n')) => return Some(MenuChoice::Resume),
+            (_, KeyCode::Esc | PAUSE) => return Some(MenuChoice::Resume),
+            _ => {}
+        }
+        None
+    }
+
+    fn choose(&mut self) -> Option<MenuChoice> {
+        match (self.page, self.selected) {
+            (Page::Main, 0) => Some(MenuChoice::Resume),
+            (Page::Main, 1) => Some(MenuChoice::Restart),
+            (Page::Main, 2) => {
+                self.page = Page::Settings;
+                self.selected = 0;
+                None
+            }
+            (Page::Main, _) => {
+                *self = PauseMenu::confirm_quit();
+                None
+            }
+            (Page::Settings, i) => match Setting::ALL.get(i) {
+                Some(&setting) => Some(MenuChoice::Change(setting)),
+                None => {
+                    self.back();
+                    None
+                }
+            },
+            (Page::ConfirmQuit, 0) => Some(MenuChoice::Quit),
+            (Page::ConfirmQuit, _) => Some(MenuChoice::Resume),
+        }
+    }
+
+    fn back(&mut self) {
+        self.page = Page::Main;
+        // Back on "Settings"
+        self.selected = 2;
+    }
+
+    /// The title and items to show, and which item is selected
+    pub fn lines(&self, settings: &Settings) -> (&'static str, Vec<String>, usize) {
+        let items = |items: &[&str]| items.iter().map(|item| item.to_string()).collect();
+        match self.page {
+            Page::Main => ("PAUSED", items(&MAIN_ITEMS), self.selected),
+            Page::Settings => {
+                let mut items = Setting::ALL
+                    .iter()
+                    .map(|setting| setting.label(settings))
+                    .collect::<Vec<String>>();
+                items.push("Back".to_string());
+                ("SETTINGS", items, self.selected)
+            }
+            Page::ConfirmQuit => ("QUIT?", items(&CONFIRM_ITEMS), self.selected),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_main_page() {
+        let keys = KeyBindings::default();
+        let mut menu = PauseMenu::new();
+        assert_eq!(menu.key(KeyCode::Enter, &keys), Some(MenuChoice::Resume));
+        assert_eq!(
+            menu.key(KeyCode::Char('r'), &keys),
+            Some(MenuChoice::Restart)
+        );
+        assert_eq!(menu.key(PAUSE, &keys), Some(MenuChoice::Resume));
+        // Quitting asks first
+        assert_eq!(menu.key(KeyCode::Up, &keys), None);
+        assert_eq!(menu.key(KeyCode::Enter, &keys), None);
+        assert_eq!(menu, PauseMenu::confirm_quit());
+        let mut menu = PauseMenu::new();
+        assert_eq!(menu.key(KeyCode::Char('q'), &keys), None);
+        assert_eq!(menu, PauseMenu::confirm_quit());
+    }
+
+    #[test]
+    fn test_rebound_keys() {
+        let keys = KeyBindings {
+            quit: KeyCode::Char('x'),
+            restart: KeyCode::F(5),
+        };
+        let mut menu = PauseMenu::new();
+        assert_eq!(menu.key(KeyCode::Char('q'), &keys), None);
+        assert_eq!(menu.key(KeyCode::Char('r'), &keys), None);
+        assert_eq!(menu.key(KeyCode::F(5), &keys), Some(MenuChoice::Restart));
+        assert_eq!(menu.key(KeyCode::Char('x'), &keys), None);
+        assert_eq!(menu, PauseMenu::confirm_quit());
+        assert_eq!(menu.key(KeyCode::Char('x'), &keys), Some(MenuChoice::Quit));
+    }
+
+    #[test]
+    fn test_settings_page() {
+        let keys = KeyBindings::default();
+        let mut menu = PauseMenu::new();
+        menu.key(KeyCode::Down, &keys);
+        menu.key(KeyCode::Down, &keys);
+        assert_eq!(menu.key(KeyCode::Enter, &keys), None);
+        let (title, items, selected) = menu.lines(&Settings::default());
+        assert_eq!(title, "SETTINGS");
+        assert_eq!(items[selected], "Theme: classic");
+        menu.key(KeyCode::Down, &keys);
+        assert_eq!(
+            menu.key(KeyCode::Enter, &keys),
+            Some(MenuChoice::Change(Setting::Animations))
+        );
+        // Escape goes back to the main page rather than resuming
+        assert_eq!(menu.key(KeyCode::Esc, &keys), None);
+        let (title, items, selected) = menu.lines(&Settings::default());
+        assert_eq!((title, items[selected].as_str()), ("PAUSED", "Settings"));
+    }
+
+    #[test]
+    fn test_confirm_quit() {
+        let keys = KeyBindings::default();
+        let mut menu = PauseMenu::confirm_quit();
+        assert_eq!(menu.key(KeyCode::Char('r'), &keys), None);
+        assert_eq!(
+            menu.key(KeyCode::Char('n'), &keys),
+            Some(MenuChoice::Resume)
+        );
+        assert_eq!(menu.key(KeyCode::Down, &keys), None);
+        assert_eq!(menu.key(KeyCode::Enter, &keys), Some(MenuChoice::Resume));
+        assert_eq!(menu.key(KeyCode::Char('y'), &keys), Some(MenuChoice::Quit));
+    }
+}
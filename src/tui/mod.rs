@@ -1,202 +1,624 @@
-use super::game::{HEIGHT, PSIZE, WIDTH};
+mod animation;
+mod app;
+mod buffer;
+mod guard;
+mod keys;
+mod menu;
+mod panel;
+mod settings;
+mod theme;
+
+use crate::game::{Event, Game, HEIGHT, PSIZE, WIDTH};
+use animation::{callout_texts, Callout, ClearAnimation, ClearFrame, LOCK_FLASH};
+use buffer::Buffer;
 use crossterm::{
     cursor, queue,
-    style::{Color, Print, ResetColor, SetForegroundColor},
+    style::Color,
     terminal::{self, size},
 };
+use keys::{key_name, KeyBindings};
+use panel::{panel_lines, PanelItem, PANEL_WIDTH};
 use std::{
-    collections::HashMap,
     io::{stdout, Write},
+    time::{Duration, Instant},
 };
+use theme::{ColorDepth, Theme};
+
+/// How long a notice stays on screen
+const NOTICE_TIME: Duration = Duration::from_secs(2);
+/// Largest cell scale: cells are drawn 2×1, 4×2 or 6×3 characters
+pub const MAX_SCALE: usize = 3;
+
+pub use app::run;
 
 pub struct Tui {
-    colors: HashMap<u8, Color>,
-    border: &'static str,
-    space: &'static str,
+    theme: Theme,
+    /// Draw with ASCII characters only
+    ascii: bool,
+    /// Shown in the help line
+    pub keys: KeyBindings,
     pub message: String,
+    /// Name of the music track playing
+    pub track: Option<String>,
+    /// Short lived message shown above the status line, and when it was
+    /// shown
+    notice: Option<(String, Instant)>,
+    /// What is on the terminal now
+    screen: Buffer,
+    /// Terminal size in columns and rows
+    size: (usize, usize),
+    /// Stats shown beside the board, when there's room for them
+    pub panel: Vec<PanelItem>,
+    /// Animate line clears and locks and show callouts for notable clears
+    pub animations: bool,
+    callouts: Vec<Callout>,
+    clear_animation: Option<ClearAnimation>,
+    /// Largest cell scale to draw at, or `None` for up to `MAX_SCALE`. The
+    /// largest scale that fits the terminal is used.
+    pub cell_size: Option<usize>,
+    /// Scale the game is drawn at now
+    scale: usize,
 }
 
 impl Tui {
     pub fn new() -> Self {
         Tui {
-            colors: [
-                Color::Black, // 0 is empty
-                Color::Cyan,
-                Color::Blue,
-                Color::Rgb {
-                    r: 255,
-                    g: 165,
-                    b: 0,
-                }, // Orange
-                Color::Yellow,
-                Color::Rgb {
-                    r: 102,
-                    g: 255,
-                    b: 102,
-                }, // Light Green
-                Color::Magenta,
-                Color::Red,
-            ]
-            .iter()
-            .enumerate()
-            .map(|(i, c)| (i as u8, *c))
-            .collect(),
-            border: "─",
-            space: " ",
+            theme: Theme::default().downgrade(ColorDepth::detect()),
+            ascii: false,
+            keys: KeyBindings::default(),
             message: String::new(),
+            track: None,
+            notice: None,
+            screen: Buffer::new(0, 0),
+            size: size().map_or((0, 0), |(w, h)| (w as usize, h as usize)),
+            panel: PanelItem::ALL.to_vec(),
+            animations: true,
+            callouts: Vec::new(),
+            clear_animation: None,
+            cell_size: None,
+            scale: 1,
         }
     }
 
-    pub fn draw_board(
-        &mut self,
-        board: &[[u8; WIDTH]; HEIGHT],
-        preview_board: &[[u8; PSIZE]; PSIZE],
-        score: usize,
-    ) {
-        let mut stdout = stdout();
-        let (width, height) = size().unwrap();
-        let (width, height) = (width as usize, height as usize);
-        let (x, y) = (width / 2 - WIDTH, height / 2 - (HEIGHT / 2));
-        queue!(stdout, terminal::Clear(terminal::ClearType::All)).unwrap();
-        self.draw_main_board(&mut stdout, board, x, y);
-        self.draw_preview_board(&mut stdout, preview_board, x, y);
-        // Message
+    /// Switches to `theme`, with its colours reduced to ones the terminal
+    /// can show. In ASCII mode everything is drawn with ASCII characters.
+    pub fn set_theme(&mut self, theme: Theme, ascii: bool) {
+        let theme = theme.downgrade(ColorDepth::detect());
+        self.theme = if ascii { theme.ascii() } else { theme };
+        self.ascii = ascii;
+    }
+
+    pub fn ascii(&self) -> bool {
+        self.ascii
+    }
+
+    /// Lays the screen out again for a new terminal size
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.size = (width as usize, height as usize);
+    }
+
+    /// Whether the game fits in the terminal
+    pub fn fits(&self) -> bool {
+        board_origin(self.size.0, self.size.1, 1).is_some()
+    }
+
+    pub fn draw_board(&mut self, game: &Game) {
+        let quit = key_name(self.keys.quit);
+        let (hold, pause) = (key_name(keys::HOLD), key_name(keys::PAUSE));
+        let help = if game.mode.endless() {
+            format!(
+                "{}: quit | 1-7: pick next | {}: gravity | {}/{}: undo/redo | {}: hold | {}: pause",
+                quit,
+                key_name(keys::GRAVITY),
+                key_name(keys::UNDO),
+                key_name(keys::REDO),
+                hold,
+                pause
+            )
+        } else {
+            format!(
+                "{}: quit | {}: restart | {}: instant drop | {}: hold | {}: pause",
+                quit,
+                key_name(self.keys.restart),
+                key_name(keys::DROP),
+                hold,
+                pause
+            )
+        };
+        self.message = format!("{}\n{}", self.status(game), help);
+        self.draw_game(game, None);
+    }
+
+    /// Draws the game hidden behind a menu with a title and items, one of
+    /// them selected
+    pub fn draw_paused(&mut self, game: &Game, title: &str, items: &[String], selected: usize) {
         self.message = format!(
-            "Score: {}\nq: quit | d: disable sound | Space: instant drop | p: pause",
-            score
+            "{}\nup/down: select | Enter: choose | {}: resume | {}: quit",
+            self.status(game),
+            key_name(keys::PAUSE),
+            key_name(self.keys.quit)
         );
-        self.draw_messages(&mut stdout, width, height);
-        stdout.flush().unwrap();
+        self.draw_game(game, Some((title, items, selected)));
     }
 
-    fn draw_main_board(
-        &self,
-        stdout: &mut std::io::Stdout,
-        board: &[[u8; WIDTH]; HEIGHT],
-        x: usize,
-        y: usize,
-    ) {
-        // Board borders
-        queue!(
-            stdout,
-            cursor::MoveTo(x as u16, y as u16),
-            SetForegroundColor(Color::White),
-            Print(format!("┌{}┐", self.border.repeat(WIDTH * 2 + 2))), // board width * 2 + 2 lines
-        )
-        .unwrap();
-        for i in 0..HEIGHT {
-            queue!(
-                stdout,
-                cursor::MoveTo(x as u16, (y + i + 1) as u16),
-                Print(format!("│{}│", self.space.repeat(WIDTH * 2 + 2))), // board width * 2 + 2 spaces
-            )
-            .unwrap();
+    fn status(&self, game: &Game) -> String {
+        let status = match game.grade() {
+            Some(grade) => format!("Score: {} | Grade: {}", game.score, grade),
+            None if game.mode.endless() => format!(
+                "Score: {} | Lines: {} | Pieces: {} | Gravity: {}",
+                game.score,
+                game.lines,
+                game.pieces,
+                if game.gravity() { "on" } else { "off" }
+            ),
+            None => format!("Score: {}", game.score),
+        };
+        match &self.track {
+            Some(track) => format!("{} | ♪ {}", status, track),
+            None => status,
+        }
+    }
+
+    /// Draws a game being played back from a replay
+    pub fn draw_replay(&mut self, game: &Game, status: &str) {
+        self.message = format!(
+            "{}\nq: quit | p: pause | left/right: seek | up/down: speed | .: step frame",
+            status
+        );
+        self.draw_game(game, None);
+    }
+
+    /// Shows callouts such as "TETRIS" or "COMBO 2" for the clear the
+    /// events describe
+    pub fn announce(&mut self, events: &[Event], game: &Game) {
+        if !self.animations {
+            return;
+        }
+        // Modes with a line clear delay keep the rows on the board for it
+        let cleared = events
+            .iter()
+            .any(|event| matches!(event, Event::LineClear(_)));
+        if cleared && game.clearing().is_empty() {
+            self.clear_animation = Some(ClearAnimation {
+                rows: game.cleared_rows().to_vec(),
+                shown: Instant::now(),
+            });
+        }
+        let texts = callout_texts(events, game);
+        if !texts.is_empty() {
+            let shown = Instant::now();
+            self.callouts = texts
+                .into_iter()
+                .map(|text| Callout { text, shown })
+                .collect();
         }
+    }
+
+    /// Whether something on the board is moving, so it needs drawing every
+    /// frame rather than only when the game changes
+    pub fn animating(&self, game: &Game) -> bool {
+        let locking = (0..HEIGHT)
+            .any(|y| (0..WIDTH).any(|x| game.lock_age(x, y).is_some_and(|age| age < LOCK_FLASH)));
+        let animated = !game.clearing().is_empty()
+            || locking
+            || self.clear_animation.is_some()
+            || !self.callouts.is_empty();
+        self.animations && animated
+    }
+
+    /// Shows `text` for a couple of seconds
+    pub fn notify(&mut self, text: String) {
+        self.notice = Some((text, Instant::now()));
+    }
+
+    fn draw_game(&mut self, game: &Game, menu: Option<(&str, &[String], usize)>) {
+        let (width, height) = self.size;
+        let mut frame = Buffer::new(width, height);
+        let max_scale = self.cell_size.unwrap_or(MAX_SCALE);
+        let Some((x, y, scale)) = layout(width, height, max_scale) else {
+            self.draw_too_small(&mut frame, width, height);
+            self.render(frame);
+            return;
+        };
+        self.scale = scale;
+        if self
+            .clear_animation
+            .as_ref()
+            .is_some_and(ClearAnimation::expired)
+        {
+            self.clear_animation = None;
+        }
+        self.draw_main_board(&mut frame, game, x, y);
+        self.draw_preview_board(&mut frame, "Next:", game.preview_board_ref(), x, y);
+        let hold_y = y + PSIZE * scale + 4;
+        self.draw_preview_board(&mut frame, "Hold:", game.hold_board_ref(), x, hold_y);
+        self.draw_panel(&mut frame, game, x, y);
+        self.draw_callouts(&mut frame, x, y);
+        if let Some((title, items, selected)) = menu {
+            self.draw_menu(&mut frame, x, y, title, items, selected);
+        }
+        self.draw_messages(&mut frame, width, height);
+        self.draw_notice(&mut frame, width, height);
+        self.render(frame);
+    }
+
+    fn draw_too_small(&self, frame: &mut Buffer, width: usize, height: usize) {
+        let (game_width, game_height) = game_size(1);
+        let lines = [
+            "Please enlarge the terminal".to_string(),
+            format!("to at least {}×{}", game_width, game_height),
+            format!("(now {}×{})", width, height),
+        ];
+        for (i, line) in lines.iter().enumerate() {
+            frame.print(
+                width.saturating_sub(line.chars().count()) / 2,
+                (height / 2 + i).saturating_sub(1),
+                line,
+                Some(Color::White),
+            );
+        }
+    }
+
+    /// Writes the parts of `frame` that changed since the last frame to the
+    /// terminal. Terminals that support synchronized updates show the
+    /// changes all at once, others ignore the sequences.
+    fn render(&mut self, mut frame: Buffer) {
+        // Catches anything outside the theme, such as the track symbol
+        if self.ascii {
+            frame.make_ascii();
+        }
+        let mut stdout = stdout();
+        let (_, height) = frame.size();
+        queue!(stdout, terminal::BeginSynchronizedUpdate).unwrap();
+        frame.draw_diff(&self.screen, &mut stdout).unwrap();
         queue!(
             stdout,
-            cursor::MoveTo(x as u16, (y + HEIGHT + 1) as u16),
-            Print(format!("└{}┘", self.border.repeat(WIDTH * 2 + 2))),
-            ResetColor,
+            cursor::MoveTo(0, height as u16),
+            terminal::EndSynchronizedUpdate
         )
         .unwrap();
+        stdout.flush().unwrap();
+        self.screen = frame;
+    }
+
+    fn draw_notice(&mut self, frame: &mut Buffer, width: usize, height: usize) {
+        let Some((text, shown)) = &self.notice else {
+            return;
+        };
+        if shown.elapsed() > NOTICE_TIME {
+            self.notice = None;
+            return;
+        }
+        // Shown in place of the help line
+        let row = height.saturating_sub(1);
+        frame.print(0, row, &" ".repeat(width), None);
+        frame.print(
+            width.saturating_sub(text.chars().count()) / 2,
+            row,
+            text,
+            Some(Color::Yellow),
+        );
+    }
+
+    fn draw_main_board(&self, frame: &mut Buffer, game: &Game, x: usize, y: usize) {
+        let board = game.board_ref();
+        let scale = self.scale;
+        // A column of padding either side of the cells inside the borders
+        self.draw_box(
+            frame,
+            x,
+            y,
+            WIDTH * 2 * scale + 2,
+            HEIGHT * scale,
+            Some(Color::White),
+        );
         // Board content
+        let clear_frame = match (game.clear_progress(), self.animations) {
+            (Some(progress), true) => Some(ClearFrame::at(progress)),
+            // Without animations cleared rows disappear straight away
+            (Some(_), false) => Some(ClearFrame::Collapse(WIDTH)),
+            (None, _) => None,
+        };
         for i in 0..HEIGHT {
+            let clearing = clear_frame.filter(|_| game.clearing().contains(&i));
             for j in 0..WIDTH {
-                if board[i][j] != 0 {
-                    queue!(
-                        stdout,
-                        cursor::MoveTo((x + j * 2 + 2) as u16, (y + i + 1) as u16),
-                        SetForegroundColor(self.colors[&board[i][j]]),
-                        Print("██"),
-                        ResetColor,
-                    )
-                    .unwrap();
+                if board[i][j] == 0
+                    || !game.cell_visible(j, i)
+                    || clearing.is_some_and(|clearing| !clearing.shows(j))
+                {
+                    continue;
+                }
+                let just_locked =
+                    self.animations && game.lock_age(j, i).is_some_and(|age| age < LOCK_FLASH);
+                let (glyph, color) = self.theme.piece(board[i][j]);
+                let color = if clearing == Some(ClearFrame::Flash) || just_locked {
+                    Color::White
+                } else {
+                    color
+                };
+                self.draw_cell(
+                    frame,
+                    x + j * 2 * scale + 2,
+                    y + i * scale + 1,
+                    glyph,
+                    color,
+                );
+            }
+        }
+        self.draw_clear_animation(frame, x, y);
+    }
+
+    /// Draws rows cleared without a line clear delay over the board where
+    /// they were, flashing and then collapsing
+    fn draw_clear_animation(&self, frame: &mut Buffer, x: usize, y: usize) {
+        let Some(animation) = &self.clear_animation else {
+            return;
+        };
+        let clear_frame = ClearFrame::at(animation.progress());
+        for (i, cells) in &animation.rows {
+            for (j, &cell) in cells.iter().enumerate() {
+                if cell == 0 || !clear_frame.shows(j) {
+                    continue;
                 }
+                let (glyph, color) = self.theme.piece(cell);
+                let color = if clear_frame == ClearFrame::Flash {
+                    Color::White
+                } else {
+                    color
+                };
+                self.draw_cell(
+                    frame,
+                    x + j * 2 * self.scale + 2,
+                    y + i * self.scale + 1,
+                    glyph,
+                    color,
+                );
             }
         }
     }
 
+    /// Draws a cell with its top left corner at (x, y), its glyph
+    /// repeated to the current scale
+    fn draw_cell(&self, frame: &mut Buffer, x: usize, y: usize, glyph: &str, color: Color) {
+        let line = glyph.repeat(self.scale);
+        for row in 0..self.scale {
+            frame.print(x, y + row, &line, Some(color));
+        }
+    }
+
+    /// Draws the callouts under the hold box, rising as they age
+    fn draw_callouts(&mut self, frame: &mut Buffer, x: usize, y: usize) {
+        self.callouts.retain(|callout| !callout.expired());
+        for (i, callout) in self.callouts.iter().enumerate() {
+            frame.print(
+                preview_x(x, self.scale),
+                y + HEIGHT * self.scale - 2 + i - callout.rise(),
+                &callout.text,
+                Some(Color::Yellow),
+            );
+        }
+    }
+
+    /// Hides the board and previews, so pausing can't be used to plan
+    /// ahead, and draws a menu over the board
+    fn draw_menu(
+        &self,
+        frame: &mut Buffer,
+        x: usize,
+        y: usize,
+        title: &str,
+        items: &[String],
+        selected: usize,
+    ) {
+        let blank = |frame: &mut Buffer, x: usize, y: usize, width: usize, height: usize| {
+            for i in 0..height {
+                frame.print(x, y + i, &" ".repeat(width), None);
+            }
+        };
+        let scale = self.scale;
+        let (board_width, board_height) = (WIDTH * 2 * scale, HEIGHT * scale);
+        blank(frame, x + 1, y + 1, board_width + 2, board_height);
+        for preview_y in [y + 3, y + PSIZE * scale + 7] {
+            blank(
+                frame,
+                preview_x(x, scale) + 1,
+                preview_y,
+                PSIZE * 2 * scale + 2,
+                PSIZE * scale,
+            );
+        }
+        let width = items
+            .iter()
+            .map(|item| item.chars().count() + 4)
+            .max()
+            .unwrap_or(0)
+            .max(title.len() + 2)
+            .min(board_width);
+        // A line for the title and a blank line under it
+        let height = items.len() + 2;
+        let menu_x = x + 1 + (board_width - width) / 2;
+        let menu_y = y + 1 + (board_height - height - 2) / 2;
+        self.draw_box(frame, menu_x, menu_y, width, height, Some(Color::White));
+        frame.print(
+            menu_x + 1 + (width - title.len()) / 2,
+            menu_y + 1,
+            title,
+            Some(Color::White),
+        );
+        for (i, item) in items.iter().enumerate() {
+            let (text, color) = if i == selected {
+                (format!("> {}", item), Color::Yellow)
+            } else {
+                (format!("  {}", item), Color::White)
+            };
+            frame.print(menu_x + 2, menu_y + 3 + i, &text, Some(color));
+        }
+    }
+
+    /// Draws the stats panel left of the board, if it fits there
+    fn draw_panel(&self, frame: &mut Buffer, game: &Game, x: usize, y: usize) {
+        let Some(panel_x) = x.checked_sub(PANEL_WIDTH + 2) else {
+            return;
+        };
+        // As many lines as fit beside the board
+        for (i, line) in panel_lines(game, &self.panel)
+            .iter()
+            .take(HEIGHT * self.scale + 2)
+            .enumerate()
+        {
+            frame.print(panel_x, y + i, line, Some(Color::White));
+        }
+    }
+
     fn draw_preview_board(
         &self,
-        stdout: &mut std::io::Stdout,
+        frame: &mut Buffer,
+        label: &str,
         preview_board: &[[u8; PSIZE]; PSIZE],
         x: usize,
         y: usize,
     ) {
+        let scale = self.scale;
+        let x = preview_x(x, scale);
         // Preview Board borders
-        queue!(
-            stdout,
-            cursor::MoveTo((x + WIDTH * 2 + PSIZE) as u16, (y + 1) as u16),
-            SetForegroundColor(Color::White),
-            Print("Next:"),
-            ResetColor,
-        )
-        .unwrap();
-        queue!(
-            stdout,
-            cursor::MoveTo((x + WIDTH * 2 + PSIZE) as u16, (y + 2) as u16),
-            Print(format!("┌{}┐", self.border.repeat(PSIZE * 2 + 2))),
-        )
-        .unwrap();
-        for i in 0..PSIZE {
-            queue!(
-                stdout,
-                cursor::MoveTo((x + WIDTH * 2 + PSIZE) as u16, (y + i + 3) as u16),
-                Print(format!("│{}│", self.space.repeat(PSIZE * 2 + 2))),
-            )
-            .unwrap();
-        }
-        queue!(
-            stdout,
-            cursor::MoveTo((x + WIDTH * 2 + PSIZE) as u16, (y + (PSIZE + 3)) as u16),
-            Print(format!("└{}┘", self.border.repeat(PSIZE * 2 + 2))),
-        )
-        .unwrap();
+        frame.print(x, y + 1, label, Some(Color::White));
+        self.draw_box(frame, x, y + 2, PSIZE * 2 * scale + 2, PSIZE * scale, None);
         // Preview Board content
         for i in 0..PSIZE {
             for j in 0..PSIZE {
                 if preview_board[i][j] != 0 {
-                    queue!(
-                        stdout,
-                        cursor::MoveTo(
-                            ((x + WIDTH * 2 + (PSIZE + (PSIZE / 2))) + j * 2) as u16,
-                            (y + i + (PSIZE - 1)) as u16
-                        ),
-                        SetForegroundColor(self.colors[&preview_board[i][j]]),
-                        Print("██"),
-                        ResetColor,
-                    )
-                    .unwrap();
+                    let (glyph, color) = self.theme.piece(preview_board[i][j]);
+                    self.draw_cell(
+                        frame,
+                        x + j * 2 * scale + 2,
+                        y + i * scale + 3,
+                        glyph,
+                        color,
+                    );
                 }
             }
         }
     }
 
-    fn draw_messages(&self, stdout: &mut std::io::Stdout, width: usize, height: usize) {
+    /// Draws a border around a `width` by `height` area, with its top left
+    /// corner at (x, y), and fills the area with the theme's background
+    fn draw_box(
+        &self,
+        frame: &mut Buffer,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        color: Option<Color>,
+    ) {
+        let [horizontal, vertical, top_left, top_right, bottom_left, bottom_right] =
+            self.theme.border;
+        let line = horizontal.to_string().repeat(width);
+        frame.print(x, y, &format!("{}{}{}", top_left, line, top_right), color);
+        for i in 0..height {
+            frame.print(x, y + i + 1, &vertical.to_string(), color);
+            frame.print(x + width + 1, y + i + 1, &vertical.to_string(), color);
+        }
+        frame.print(
+            x,
+            y + height + 1,
+            &format!("{}{}{}", bottom_left, line, bottom_right),
+            color,
+        );
+        if let Some(background) = self.theme.background {
+            frame.paint(x + 1, y + 1, width, height, background);
+        }
+    }
+
+    /// Clears the screen and shows a single centred line of text
+    pub fn draw_prompt(&mut self, text: &str) {
+        let (width, height) = self.size;
+        let mut frame = Buffer::new(width, height);
+        frame.print(
+            width.saturating_sub(text.chars().count()) / 2,
+            height / 2,
+            text,
+            Some(Color::White),
+        );
+        self.render(frame);
+    }
+
+    fn draw_messages(&self, frame: &mut Buffer, width: usize, height: usize) {
         if self.message.is_empty() {
             return;
         }
         let messages = self.message.split('\n').collect::<Vec<&str>>();
-        let m_offsetx = ((width - messages[0].len()) / 2) as u16;
-        let m_offsety = (height - 3) as u16;
-        queue!(
-            stdout,
-            cursor::MoveTo(m_offsetx, m_offsety),
-            SetForegroundColor(Color::White),
-            Print(messages[0]),
-            ResetColor,
-        )
-        .unwrap();
-        let m_offsetx = ((width - messages[1].len()) / 2) as u16;
-        let m_offsety = (height - 2) as u16;
-        queue!(
-            stdout,
-            cursor::MoveTo(m_offsetx, m_offsety),
-            SetForegroundColor(Color::White),
-            Print(messages[1]),
-            ResetColor,
-        )
-        .unwrap();
-        queue!(stdout, cursor::MoveTo(0, height as u16)).unwrap();
+        let m_offsetx = width.saturating_sub(messages[0].chars().count()) / 2;
+        let m_offsety = height.saturating_sub(2);
+        frame.print(m_offsetx, m_offsety, messages[0], Some(Color::White));
+        let m_offsetx = width.saturating_sub(messages[1].chars().count()) / 2;
+        let m_offsety = height.saturating_sub(1);
+        frame.print(m_offsetx, m_offsety, messages[1], Some(Color::White));
+    }
+}
+
+/// Columns and rows taken up at a cell scale: the board and the preview
+/// boards beside it, with their borders, and the status and help lines
+/// under them
+fn game_size(scale: usize) -> (usize, usize) {
+    ((WIDTH + PSIZE) * 2 * scale + 8, HEIGHT * scale + 2 + 2)
+}
+
+/// Left edge of the preview boards for a board at `x`
+fn preview_x(x: usize, scale: usize) -> usize {
+    x + WIDTH * 2 * scale + 4
+}
+
+/// Top left corner of the board drawn at `scale` for a terminal size, or
+/// `None` if the game doesn't fit
+fn board_origin(width: usize, height: usize, scale: usize) -> Option<(usize, usize)> {
+    let (game_width, game_height) = game_size(scale);
+    if width < game_width || height < game_height {
+        return None;
+    }
+    // The board is centred, unless that pushes the previews off screen
+    let x = (width / 2)
+        .saturating_sub(WIDTH * scale)
+        .min(width - game_width);
+    let y = (height - game_height) / 2;
+    Some((x, y))
+}
+
+/// Top left corner of the board and the largest scale up to `max_scale`
+/// the game fits the terminal at, or `None` if it doesn't fit at all
+fn layout(width: usize, height: usize, max_scale: usize) -> Option<(usize, usize, usize)> {
+    (1..=max_scale)
+        .rev()
+        .find_map(|scale| board_origin(width, height, scale).map(|(x, y)| (x, y, scale)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_board_origin() {
+        let (width, height) = game_size(1);
+        assert_eq!((width, height), (40, 24));
+        assert_eq!(board_origin(0, 0, 1), None);
+        assert_eq!(board_origin(width - 1, height, 1), None);
+        assert_eq!(board_origin(width, height - 1, 1), None);
+        assert_eq!(board_origin(width, height, 1), Some((0, 0)));
+        assert_eq!(board_origin(80, 40, 1), Some((40 - WIDTH, 8)));
+    }
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(game_size(2), (72, 44));
+        assert_eq!(game_size(3), (104, 64));
+        assert_eq!(layout(80, 40, MAX_SCALE), Some((40 - WIDTH, 8, 1)));
+        assert_eq!(layout(80, 50, MAX_SCALE), Some((80 - 72, 3, 2)));
+        assert_eq!(
+            layout(200, 64, MAX_SCALE).map(|(_, _, scale)| scale),
+            Some(3)
+        );
+        // A smaller size is kept to even when a larger one fits
+        assert_eq!(layout(200, 64, 1).map(|(_, _, scale)| scale), Some(1));
+        assert_eq!(layout(39, 64, MAX_SCALE), None);
     }
 }
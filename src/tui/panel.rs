@@ -0,0 +1,133 @@
+use crate::game::Game;
+
+/// Columns taken up by the stats panel
+pub const PANEL_WIDTH: usize = 18;
+/// Shape letters in `ShapeType` order, for the piece distribution
+const SHAPE_LETTERS: [char; 7] = ['I', 'J', 'L', 'O', 'S', 'T', 'Z'];
+
+/// A group of stats that can be shown in the side panel
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum PanelItem {
+    Time,
+    Level,
+    Lines,
+    Pieces,
+    /// Pieces per second
+    Pps,
+    /// Keys per piece
+    Kpp,
+    /// Attack per minute
+    Apm,
+    /// Singles, doubles, triples and tetrises
+    Clears,
+    TSpins,
+    Combo,
+    /// How many of each shape were placed
+    Distribution,
+}
+
+impl PanelItem {
+    pub const ALL: [PanelItem; 11] = [
+        PanelItem::Time,
+        PanelItem::Level,
+        PanelItem::Lines,
+        PanelItem::Pieces,
+        PanelItem::Pps,
+        PanelItem::Kpp,
+        PanelItem::Apm,
+        PanelItem::Clears,
+        PanelItem::TSpins,
+        PanelItem::Combo,
+        PanelItem::Distribution,
+    ];
+
+    /// The name used in the settings file
+    pub fn name(self) -> &'static str {
+        match self {
+            PanelItem::Time => "time",
+            PanelItem::Level => "level",
+            PanelItem::Lines => "lines",
+            PanelItem::Pieces => "pieces",
+            PanelItem::Pps => "pps",
+            PanelItem::Kpp => "kpp",
+            PanelItem::Apm => "apm",
+            PanelItem::Clears => "clears",
+            PanelItem::TSpins => "tspins",
+            PanelItem::Combo => "combo",
+            PanelItem::Distribution => "distribution",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<PanelItem> {
+        PanelItem::ALL.into_iter().find(|item| item.name() == name)
+    }
+
+    /// The labelled values the item adds to the panel
+    pub fn rows(self, game: &Game) -> Vec<(String, String)> {
+        let stats = &game.stats;
+        let row = |label: &str, value: String| (label.to_string(), value);
+        match self {
+            PanelItem::Time => {
+                let time = game.play_time().as_secs_f64();
+                vec![row(
+                    "Time",
+                    format!("{}:{:04.1}", (time / 60.0) as u64, time % 60.0),
+                )]
+            }
+            PanelItem::Level => vec![row("Level", game.level().to_string())],
+            PanelItem::Lines => vec![row("Lines", game.lines.to_string())],
+            PanelItem::Pieces => vec![row("Pieces", game.pieces.to_string())],
+            PanelItem::Pps => vec![row("PPS", format!("{:.2}", game.pieces_per_second()))],
+            PanelItem::Kpp => vec![row("KPP", format!("{:.2}", game.keys_per_piece()))],
+            PanelItem::Apm => vec![row("APM", format!("{:.1}", game.attack_per_minute()))],
+            PanelItem::Clears => ["Singles", "Doubles", "Triples", "Tetrises"]
+                .iter()
+                .zip(stats.clears)
+                .map(|(label, count)| row(label, count.to_string()))
+                .collect(),
+            PanelItem::TSpins => vec![row("T-spins", stats.t_spins.to_string())],
+            PanelItem::Combo => vec![row("Max combo", stats.max_combo.to_string())],
+            PanelItem::Distribution => SHAPE_LETTERS
+                .iter()
+                .zip(stats.distribution)
+                .map(|(letter, count)| row(&letter.to_string(), count.to_string()))
+                .collect(),
+        }
+    }
+}
+
+/// The panel's lines for `items`, each label and value spread across
+/// `PANEL_WIDTH`
+pub fn panel_lines(game: &Game, items: &[PanelItem]) -> Vec<String> {
+    items
+        .iter()
+        .flat_map(|item| item.rows(game))
+        .map(|(label, value)| {
+            let gap = PANEL_WIDTH.saturating_sub(label.chars().count());
+            format!("{}{:>gap$}", label, value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        for item in PanelItem::ALL {
+            assert_eq!(PanelItem::from_name(item.name()), Some(item));
+        }
+        assert_eq!(PanelItem::from_name("score"), None);
+    }
+
+    #[test]
+    fn test_panel_lines() {
+        let game = Game::new();
+        let lines = panel_lines(&game, &[PanelItem::Time, PanelItem::Clears]);
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "Time        0:00.0");
+        assert_eq!(lines[4], "Tetrises         0");
+        assert!(lines.iter().all(|line| line.chars().count() == PANEL_WIDTH));
+    }
+}
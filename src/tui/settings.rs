@@ -0,0 +1,270 @@
+use super::keys::{key_name, parse_key, KeyBindings};
+use super::panel::PanelItem;
+use super::MAX_SCALE;
+use crate::game::sound::{Volume, VOLUME_STEPS};
+use std::path::{Path, PathBuf};
+
+/// Player settings, read from a line based `key value` text file. Missing
+/// keys keep their defaults and unknown ones are ignored.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Settings {
+    /// Directory sound effects are loaded from
+    pub sound_pack: PathBuf,
+    /// Use effects from the sound pack where it has them. When off, every
+    /// effect is synthesized.
+    pub sound_files: bool,
+    pub volume: Volume,
+    /// Directory of music tracks to play
+    pub music_dir: PathBuf,
+    pub shuffle: bool,
+    pub repeat: bool,
+    /// Stats shown in the side panel, in order. Empty hides the panel.
+    pub stats_panel: Vec<PanelItem>,
+    /// Animate line clears and locks and show clear callouts
+    pub animations: bool,
+    /// Name of a built in theme or of a theme file in the themes directory
+    pub theme: String,
+    /// Draw with ASCII characters only. `None` decides from the locale.
+    pub ascii: Option<bool>,
+    /// Largest cell scale to draw at, from 1 for 2×1 cells up to
+    /// `MAX_SCALE`. `None` uses the largest that fits.
+    pub cell_size: Option<usize>,
+    pub keys: KeyBindings,
+    /// Problems found reading the settings file, to show the player. These
+    /// aren't saved.
+    pub warnings: Vec<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            sound_pack: PathBuf::from("sounds"),
+            sound_files: true,
+            volume: Volume::default(),
+            music_dir: PathBuf::from("music"),
+            shuffle: false,
+            repeat: true,
+            stats_panel: PanelItem::ALL.to_vec(),
+            animations: true,
+            theme: "classic".to_string(),
+            ascii: None,
+            cell_size: None,
+            keys: KeyBindings::default(),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// The sound pack to load effects from, if files are preferred
+    pub fn sound_pack(&self) -> Option<&Path> {
+        self.sound_files.then_some(self.sound_pack.as_path())
+    }
+
+    /// Reads the settings at `path`, falling back to the defaults if there
+    /// is no settings file
+    pub fn load(path: &Path) -> Settings {
+        std::fs::read_to_string(path)
+            .map(|text| Settings::parse(&text))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_text())
+    }
+
+    fn to_text(&self) -> String {
+        [
+            format!("sound_pack {}", self.sound_pack.display()),
+            format!(
+                "sound_effects {}",
+                if self.sound_files { "files" } else { "synth" }
+            ),
+            format!("music_volume {}", self.volume.music),
+            format!("sfx_volume {}", self.volume.sfx),
+            format!("muted {}", self.volume.muted as u8),
+            format!("music_dir {}", self.music_dir.display()),
+            format!("shuffle {}", self.shuffle as u8),
+            format!("repeat {}", self.repeat as u8),
+            format!("stats_panel {}", panel_text(&self.stats_panel)),
+            format!("animations {}", self.animations as u8),
+            format!("theme {}", self.theme),
+            format!(
+                "ascii {}",
+                self.ascii
+                    .map_or("auto", |ascii| if ascii { "1" } else { "0" })
+            ),
+            format!(
+                "cell_size {}",
+                self.cell_size.map_or("auto".to_string(), |scale| format!(
+                    "{}x{}",
+                    scale * 2,
+                    scale
+                ))
+            ),
+            format!("key_quit {}", key_name(self.keys.quit)),
+            format!("key_restart {}", key_name(self.keys.restart)),
+        ]
+        .join("\n")
+            + "\n"
+    }
+
+    fn parse(text: &str) -> Settings {
+        let mut settings = Settings::default();
+        for line in text.lines() {
+            let Some((key, value)) = line.trim().split_once(' ') else {
+                continue;
+            };
+            let value = value.trim();
+            match (key, value) {
+                ("sound_pack", _) => settings.sound_pack = PathBuf::from(value),
+                ("sound_effects", "files") => settings.sound_files = true,
+                ("sound_effects", "synth") => settings.sound_files = false,
+                ("music_volume", _) => {
+                    if let Some(volume) = parse_volume(value) {
+                        settings.volume.music = volume;
+                    }
+                }
+                ("sfx_volume", _) => {
+                    if let Some(volume) = parse_volume(value) {
+                        settings.volume.sfx = volume;
+                    }
+                }
+                ("muted", _) => settings.volume.muted = value == "1",
+                ("music_dir", _) => settings.music_dir = PathBuf::from(value),
+                ("shuffle", _) => settings.shuffle = value == "1",
+                ("repeat", _) => settings.repeat = value == "1",
+                ("key_quit", _) => {
+                    if let Some(key) = parse_key(value) {
+                        settings.keys.quit = key;
+                    }
+                }
+                ("key_restart", _) => {
+                    if let Some(key) = parse_key(value) {
+                        settings.keys.restart = key;
+                    }
+                }
+                ("cell_size", "auto") => settings.cell_size = None,
+                ("cell_size", _) => {
+                    if let Some(scale) = parse_cell_size(value) {
+                        settings.cell_size = Some(scale);
+                    }
+                }
+                ("ascii", "auto") => settings.ascii = None,
+                ("ascii", _) => settings.ascii = Some(value == "1"),
+                ("theme", _) => settings.theme = value.to_string(),
+                ("animations", _) => settings.animations = value == "1",
+                ("stats_panel", "off") => settings.stats_panel = Vec::new(),
+                ("stats_panel", _) => {
+                    settings.stats_panel = value
+                        .split(',')
+                        .filter_map(|name| PanelItem::from_name(name.trim()))
+                        .collect()
+                }
+                _ => {}
+            }
+        }
+        settings.warnings = settings.keys.check();
+        settings
+    }
+}
+
+fn parse_volume(value: &str) -> Option<u8> {
+    value.parse().ok().filter(|&volume| volume <= VOLUME_STEPS)
+}
+
+/// Reads a cell size like `4x2` as its scale
+fn parse_cell_size(value: &str) -> Option<usize> {
+    let (width, height) = value.split_once('x')?;
+    let scale = height.parse().ok()?;
+    (width.parse() == Ok(scale * 2) && (1..=MAX_SCALE).contains(&scale)).then_some(scale)
+}
+
+/// Comma separated item names, or `off` for no panel
+fn panel_text(items: &[PanelItem]) -> String {
+    if items.is_empty() {
+        "off".to_string()
+    } else {
+        items
+            .iter()
+            .map(|item| item.name())
+            .collect::<Vec<&str>>()
+            .join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyCode;
+
+    #[test]
+    fn test_parse() {
+        let settings =
+            Settings::parse("# comment\nsound_pack packs/retro\nsound_effects synth\nvolume 3\n");
+        assert_eq!(settings.sound_pack, PathBuf::from("packs/retro"));
+        assert_eq!(settings.sound_pack(), None);
+        assert_eq!(Settings::parse(""), Settings::default());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let settings = Settings {
+            sound_files: false,
+            shuffle: true,
+            animations: false,
+            theme: "monochrome".to_string(),
+            ascii: Some(false),
+            cell_size: Some(2),
+            keys: KeyBindings {
+                quit: KeyCode::Char('x'),
+                restart: KeyCode::F(4),
+            },
+            volume: Volume {
+                music: 3,
+                sfx: VOLUME_STEPS,
+                muted: true,
+            },
+            ..Settings::default()
+        };
+        assert_eq!(Settings::parse(&settings.to_text()), settings);
+        // Out of range volumes are ignored
+        let settings = Settings::parse("music_volume 11\nsfx_volume x\n");
+        assert_eq!(settings.volume, Volume::default());
+    }
+
+    #[test]
+    fn test_key_conflicts() {
+        // Space is instant drop, so restart stays on the default key
+        let settings = Settings::parse("key_quit x\nkey_restart space\n");
+        assert_eq!(settings.keys.quit, KeyCode::Char('x'));
+        assert_eq!(settings.keys.restart, KeyCode::Char('r'));
+        assert_eq!(
+            settings.warnings,
+            ["key_restart space is used for instant drop, so r is kept"]
+        );
+        assert!(!settings.to_text().contains("space"));
+    }
+
+    #[test]
+    fn test_stats_panel() {
+        let settings = Settings::parse("stats_panel pps, apm,score\n");
+        assert_eq!(settings.stats_panel, [PanelItem::Pps, PanelItem::Apm]);
+        let settings = Settings {
+            stats_panel: Vec::new(),
+            ..Settings::default()
+        };
+        assert!(settings.to_text().contains("stats_panel off\n"));
+        assert_eq!(Settings::parse(&settings.to_text()), settings);
+    }
+
+    #[test]
+    fn test_cell_size() {
+        assert_eq!(Settings::parse("cell_size 6x3\n").cell_size, Some(3));
+        assert_eq!(Settings::parse("cell_size 2x1\n").cell_size, Some(1));
+        assert_eq!(Settings::parse("cell_size auto\n").cell_size, None);
+        for value in ["4x3", "8x4", "0x0", "big"] {
+            assert_eq!(parse_cell_size(value), None);
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use crossterm::style::Color;
+use std::path::Path;
+
+/// Colours of the 16 colour ANSI palette, as xterm draws them
+const ANSI_16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (128, 0, 0)),
+    (Color::DarkGreen, (0, 128, 0)),
+    (Color::DarkYellow, (128, 128, 0)),
+    (Color::DarkBlue, (0, 0, 128)),
+    (Color::DarkMagenta, (128, 0, 128)),
+    (Color::DarkCyan, (0, 128, 128)),
+    (Color::Grey, (192, 192, 192)),
+    (Color::DarkGrey, (128, 128, 128)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (0, 0, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+/// ASCII glyphs that tell pieces apart without colour
+const ASCII_PATTERNS: [&str; 7] = ["[]", "()", "{}", "<>", "##", "%%", "@@"];
+/// Levels of each channel in the 6×6×6 colour cube of the 256 colour
+/// palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// How the board and pieces look. Piece colours and glyphs are indexed by
+/// `ShapeType as usize - 1`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Theme {
+    pub pieces: [Color; 7],
+    /// Two characters drawn for each cell of a piece
+    pub glyphs: [String; 7],
+    /// Horizontal, vertical, then the top left, top right, bottom left and
+    /// bottom right corners
+    pub border: [char; 6],
+    /// Behind the board and the previews. `None` leaves the terminal's own.
+    pub background: Option<Color>,
+}
+
+impl Theme {
+    pub const BUILT_IN: [&'static str; 4] = ["classic", "colorblind", "monochrome", "pastel"];
+
+    /// The built in theme called `name`
+    pub fn built_in(name: &str) -> Option<Theme> {
+        let classic = Theme {
+            pieces: [
+                Color::Cyan,
+                Color::Blue,
+                rgb(255, 165, 0), // Orange
+                Color::Yellow,
+                rgb(102, 255, 102), // Light Green
+                Color::Magenta,
+                Color::Red,
+            ],
+            glyphs: ["██"; 7].map(String::from),
+            border: ['─', '│', '┌', '┐', '└', '┘'],
+            background: None,
+        };
+        match name {
+            "classic" => Some(classic),
+            // Okabe-Ito palette, told apart with any kind of colour blindness
+            "colorblind" => Some(Theme {
+                pieces: [
+                    rgb(86, 180, 233),
+                    rgb(0, 114, 178),
+                    rgb(230, 159, 0),
+                    rgb(240, 228, 66),
+                    rgb(0, 158, 115),
+                    rgb(204, 121, 167),
+                    rgb(213, 94, 0),
+                ],
+                ..classic
+            }),
+            // Pieces told apart by pattern alone
+            "monochrome" => Some(Theme {
+                pieces: [Color::White; 7],
+                glyphs: ["██", "▓▓", "▒▒", "[]", "░░", "<>", "##"].map(String::from),
+                border: ['═', '║', '╔', '╗', '╚', '╝'],
+                ..classic
+            }),
+            "pastel" => Some(Theme {
+                pieces: [
+                    rgb(160, 231, 229),
+                    rgb(166, 177, 225),
+                    rgb(255, 200, 150),
+                    rgb(255, 241, 166),
+                    rgb(180, 248, 200),
+                    rgb(220, 182, 232),
+                    rgb(255, 174, 188),
+                ],
+                border: ['─', '│', '╭', '╮', '╰', '╯'],
+                background: Some(rgb(40, 42, 54)),
+                ..classic
+            }),
+            _ => None,
+        }
+    }
+
+    /// The theme called `name`: `<name>.theme` in `dir` if there is one,
+    /// otherwise a built in theme. Unknown themes fall back to classic.
+    pub fn load(name: &str, dir: &Path) -> Theme {
+        match std::fs::read_to_string(dir.join(format!("{}.theme", name))) {
+            Ok(text) => Theme::parse(&text),
+            Err(_) => Theme::built_in(name).unwrap_or_default(),
+        }
+    }
+
+    /// Reads a theme from a line based `key value` text. A `base` key
+    /// starts from that built in theme, classic otherwise, and the other
+    /// keys change it:
+    ///
+    /// ```text
+    /// base monochrome
+    /// T #ff00ff
+    /// glyph_T ()
+    /// border ─│┌┐└┘
+    /// background 236
+    /// ```
+    fn parse(text: &str) -> Theme {
+        let mut theme = Theme::default();
+        let lines = text.lines().filter_map(|line| line.trim().split_once(' '));
+        // The base comes first, whatever line it's on
+        if let Some((_, name)) = lines.clone().find(|&(key, _)| key == "base") {
+            theme = Theme::built_in(name.trim()).unwrap_or_default();
+        }
+        for (key, value) in lines {
+            let value = value.trim();
+            let piece = |key: &str| "IJLOSTZ".find(key).filter(|_| key.len() == 1);
+            if let Some(i) = piece(key) {
+                if let Some(color) = parse_color(value) {
+                    theme.pieces[i] = color;
+                }
+            } else if let Some(i) = key.strip_prefix("glyph_").and_then(piece) {
+                if value.chars().count() == 2 {
+                    theme.glyphs[i] = value.to_string();
+                }
+            } else if key == "border" {
+                if let Ok(border) = value.chars().collect::<Vec<char>>().try_into() {
+                    theme.border = border;
+                }
+            } else if key == "background" {
+                theme.background = parse_color(value);
+            }
+        }
+        theme
+    }
+
+    /// The glyph and colour a board cell is drawn with
+    pub fn piece(&self, cell: u8) -> (&str, Color) {
+        let i = cell as usize - 1;
+        (&self.glyphs[i], self.pieces[i])
+    }
+
+    /// The theme drawn with ASCII characters only. Pieces become `[]`, or
+    /// different patterns if some share a colour.
+    pub fn ascii(mut self) -> Theme {
+        let shared = (1..7).any(|i| self.pieces[..i].contains(&self.pieces[i]));
+        for i in 0..self.glyphs.len() {
+            if self.glyphs[i].is_ascii() {
+                continue;
+            }
+            // A pattern no other piece has
+            let pattern = if shared {
+                ASCII_PATTERNS
+                    .into_iter()
+                    .find(|pattern| !self.glyphs.iter().any(|glyph| glyph == pattern))
+                    .unwrap_or("[]")
+            } else {
+                "[]"
+            };
+            self.glyphs[i] = pattern.to_string();
+        }
+        if !self.border.iter().all(char::is_ascii) {
+            self.border = ['-', '|', '+', '+', '+', '+'];
+        }
+        self
+    }
+
+    /// The theme with its colours reduced to ones the terminal can show
+    pub fn downgrade(mut self, depth: ColorDepth) -> Theme {
+        self.pieces = self.pieces.map(|color| depth.convert(color));
+        self.background = self.background.map(|color| depth.convert(color));
+        self
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::built_in("classic").unwrap()
+    }
+}
+
+/// How many colours a terminal can show
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl ColorDepth {
+    /// Works out the terminal's colour depth from the environment
+    pub fn detect() -> ColorDepth {
+        ColorDepth::from_env(
+            std::env::var("COLORTERM").ok().as_deref(),
+            std::env::var("TERM").ok().as_deref(),
+        )
+    }
+
+    /// The colour depth for the `COLORTERM` and `TERM` variables
+    pub fn from_env(colorterm: Option<&str>, term: Option<&str>) -> ColorDepth {
+        if matches!(colorterm, Some("truecolor" | "24bit")) {
+            ColorDepth::TrueColor
+        } else if term.is_some_and(|term| term.contains("256color")) {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+
+    /// The closest colour to `color` at this depth
+    pub fn convert(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::TrueColor, _) => color,
+            (ColorDepth::Ansi256, Color::Rgb { r, g, b }) => {
+                let level = |v: u8| nearest(&CUBE_LEVELS, |&level| distance(level, v)).0 as u8;
+                Color::AnsiValue(16 + 36 * level(r) + 6 * level(g) + level(b))
+            }
+            (ColorDepth::Ansi256, _) => color,
+            (ColorDepth::Ansi16, Color::Rgb { r, g, b }) => nearest_16((r, g, b)),
+            (ColorDepth::Ansi16, Color::AnsiValue(value)) => nearest_16(ansi_rgb(value)),
+            (ColorDepth::Ansi16, _) => color,
+        }
+    }
+}
+
+fn rgb(r: u8, g: u8, b: u8) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+/// Whether to draw with ASCII only, from the locale variables in the order
+/// they take effect. A locale that isn't UTF-8 means ASCII, and no locale
+/// at all means ASCII on Unix.
+pub fn detect_ascii() -> bool {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()));
+    locale_is_ascii(locale.as_deref())
+}
+
+fn locale_is_ascii(locale: Option<&str>) -> bool {
+    match locale {
+        Some(locale) => {
+            let locale = locale.to_lowercase();
+            !locale.contains("utf-8") && !locale.contains("utf8")
+        }
+        None => cfg!(unix),
+    }
+}
+
+/// Reads a colour name such as `dark_red`, a `#rrggbb` hex colour or a
+/// 256 colour palette number
+fn parse_color(value: &str) -> Option<Color> {
+    if let Some(hex) = value.strip_prefix('#').filter(|hex| hex.len() == 6) {
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return Some(rgb(channel(0)?, channel(2)?, channel(4)?));
+    }
+    if let Ok(value) = value.parse() {
+        return Some(Color::AnsiValue(value));
+    }
+    Color::try_from(value).ok()
+}
+
+fn distance(a: u8, b: u8) -> u32 {
+    (a as i32 - b as i32).unsigned_abs().pow(2)
+}
+
+/// The index and value of the item with the smallest `distance`
+fn nearest<T>(items: &[T], distance: impl Fn(&T) -> u32) -> (usize, &T) {
+    items
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, item)| distance(item))
+        .unwrap()
+}
+
+fn nearest_16((r, g, b): (u8, u8, u8)) -> Color {
+    nearest(&ANSI_16, |(_, (r2, g2, b2))| {
+        distance(r, *r2) + distance(g, *g2) + distance(b, *b2)
+    })
+    .1
+     .0
+}
+
+/// The RGB value of a colour in the 256 colour palette
+fn ansi_rgb(value: u8) -> (u8, u8, u8) {
+    match value {
+        0..=15 => ANSI_16[value as usize].1,
+        16..=231 => {
+            let i = value as usize - 16;
+            (
+                CUBE_LEVELS[i / 36],
+                CUBE_LEVELS[i / 6 % 6],
+                CUBE_LEVELS[i % 6],
+            )
+        }
+        // 24 step greyscale ramp
+        _ => {
+            let grey = 8 + (value - 232) * 10;
+            (grey, grey, grey)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_built_in() {
+        for name in Theme::BUILT_IN {
+            assert!(Theme::built_in(name).is_some());
+        }
+        let theme = Theme::load("no such theme", Path::new("no such dir"));
+        assert_eq!(theme, Theme::default());
+        // Monochrome pieces differ by glyph
+        let monochrome = Theme::built_in("monochrome").unwrap();
+        let glyphs = (1..=7).map(|cell| monochrome.piece(cell).0);
+        assert_eq!(glyphs.collect::<std::collections::HashSet<_>>().len(), 7);
+    }
+
+    #[test]
+    fn test_parse() {
+        let theme = Theme::parse(
+            "T #ff00ff\nglyph_T ()\nglyph_I x\nborder -|++++\nbackground 236\nbase pastel\nZ nope\n",
+        );
+        let pastel = Theme::built_in("pastel").unwrap();
+        assert_eq!(theme.piece(6), ("()", rgb(255, 0, 255)));
+        assert_eq!(theme.piece(1), ("██", pastel.pieces[0]));
+        assert_eq!(theme.pieces[6], pastel.pieces[6]);
+        assert_eq!(theme.border, ['-', '|', '+', '+', '+', '+']);
+        assert_eq!(theme.background, Some(Color::AnsiValue(236)));
+        assert_eq!(parse_color("dark_red"), Some(Color::DarkRed));
+    }
+
+    #[test]
+    fn test_ascii() {
+        let classic = Theme::default().ascii();
+        assert_eq!(classic.piece(1).0, "[]");
+        assert_eq!(classic.border, ['-', '|', '+', '+', '+', '+']);
+        let monochrome = Theme::built_in("monochrome").unwrap().ascii();
+        assert!(monochrome.glyphs.iter().all(|glyph| glyph.is_ascii()));
+        let glyphs = (1..=7).map(|cell| monochrome.piece(cell).0);
+        assert_eq!(glyphs.collect::<std::collections::HashSet<_>>().len(), 7);
+
+        assert!(!locale_is_ascii(Some("en_GB.UTF-8")));
+        assert!(!locale_is_ascii(Some("C.utf8")));
+        assert!(locale_is_ascii(Some("C")));
+        assert!(locale_is_ascii(Some("de_DE.ISO-8859-1")));
+    }
+
+    #[test]
+    fn test_color_depth() {
+        assert_eq!(
+            ColorDepth::from_env(Some("truecolor"), Some("xterm")),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(
+            ColorDepth::from_env(None, Some("xterm-256color")),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(ColorDepth::from_env(None, None), ColorDepth::Ansi16);
+
+        let orange = rgb(255, 165, 0);
+        assert_eq!(ColorDepth::TrueColor.convert(orange), orange);
+        assert_eq!(ColorDepth::Ansi256.convert(orange), Color::AnsiValue(214));
+        assert_eq!(ColorDepth::Ansi16.convert(orange), Color::Yellow);
+        assert_eq!(
+            ColorDepth::Ansi16.convert(Color::AnsiValue(17)),
+            Color::DarkBlue
+        );
+        assert_eq!(ColorDepth::Ansi16.convert(Color::Cyan), Color::Cyan);
+    }
+}